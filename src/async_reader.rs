@@ -0,0 +1,68 @@
+use crate::packet::{resync, Packet, PacketData, RESYNC_WINDOW};
+use std::convert::TryInto;
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+// reads packets off an async byte stream 188 bytes at a time. When a window doesn't start
+// on a sync byte it reads ahead up to RESYNC_WINDOW and skips to wherever resync finds the
+// packet boundaries again
+pub struct AsyncTsReader<R: AsyncRead + Unpin> {
+    inner: R,
+    buf: Vec<u8>,
+    eof: bool,
+    trailing: usize,
+}
+
+impl<R: AsyncRead + Unpin> AsyncTsReader<R> {
+    pub fn new(inner: R) -> AsyncTsReader<R> {
+        return AsyncTsReader {
+            inner: inner,
+            buf: Vec::with_capacity(RESYNC_WINDOW),
+            eof: false,
+            trailing: 0,
+        };
+    }
+
+    // None once the stream ends, a partial packet at the end is dropped and counted by
+    // trailing_bytes
+    pub async fn next_packet(&mut self) -> io::Result<Option<Packet>> {
+        loop {
+            self.fill(188).await?;
+            if self.buf.len() < 188 {
+                self.trailing = self.buf.len();
+                return Ok(None);
+            }
+            if self.buf[0] == 0x47 {
+                let data: PacketData = self.buf[..188].try_into().unwrap();
+                self.buf.drain(..188);
+                return Ok(Some(Packet::new(data)));
+            }
+            self.fill(RESYNC_WINDOW).await?;
+            let skip = resync(&self.buf).unwrap_or(self.buf.len().min(RESYNC_WINDOW));
+            self.buf.drain(..skip.max(1));
+        }
+    }
+
+    // bytes of a partial packet left when the stream ended, 0 for a clean end. Only set
+    // once next_packet has returned None
+    pub fn trailing_bytes(&self) -> usize {
+        return self.trailing;
+    }
+
+    pub fn into_inner(self) -> R {
+        return self.inner;
+    }
+
+    async fn fill(&mut self, want: usize) -> io::Result<()> {
+        let mut chunk = [0u8; RESYNC_WINDOW];
+        while self.buf.len() < want && !self.eof {
+            let needed = want - self.buf.len();
+            let read = self.inner.read(&mut chunk[..needed]).await?;
+            if read == 0 {
+                self.eof = true;
+            }
+            self.buf.extend_from_slice(&chunk[..read]);
+        }
+        return Ok(());
+    }
+}
@@ -0,0 +1,107 @@
+// elementary stream level helpers, working on reassembled PES payloads
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AacFraming {
+    // 12 bit 0xFFF syncword, stream type 0x0F
+    Adts,
+    // LOAS AudioSyncStream carrying LATM, 11 bit 0x2B7 syncword, stream type 0x11
+    Loas,
+    Unknown,
+}
+
+pub fn detect_aac_framing(payload: &[u8]) -> AacFraming {
+    if payload.len() < 2 {
+        return AacFraming::Unknown;
+    }
+    if payload[0] == 0xFF && payload[1] & 0xF0 == 0xF0 {
+        return AacFraming::Adts;
+    }
+    if payload[0] == 0x56 && payload[1] & 0xE0 == 0xE0 {
+        return AacFraming::Loas;
+    }
+    return AacFraming::Unknown;
+}
+
+// one SMPTE 2038 anc_packet. The 10 bit DID, SDID and data_count words carry parity in their
+// top two bits, the 8 bit values here have it dropped. user_data_words are kept whole
+#[derive(Debug, Clone, PartialEq)]
+pub struct AncPacket {
+    pub c_not_y_channel: bool,
+    pub line_number: u16,
+    pub horizontal_offset: u16,
+    pub did: u8,
+    pub sdid: u8,
+    pub data_count: u8,
+    pub user_data_words: Vec<u16>,
+    pub checksum_word: u16,
+}
+
+// walks the anc_packets packed back to back in a SMPTE 2038 PES payload (the bytes after the
+// PES header), each padded out to a byte boundary with 1 bits. Stops at the 0xFF stuffing
+// that fills out the PES or at a packet running off the end
+pub struct AncPackets<'a> {
+    data: &'a [u8],
+    // bit position into data
+    pos: usize,
+}
+
+pub fn anc_packets(payload: &[u8]) -> AncPackets<'_> {
+    return AncPackets {
+        data: payload,
+        pos: 0,
+    };
+}
+
+impl<'a> AncPackets<'a> {
+    fn read(&mut self, bits: usize) -> u16 {
+        let mut value = 0u16;
+        for _ in 0..bits {
+            let bit = (self.data[self.pos / 8] >> (7 - self.pos % 8)) & 1;
+            value = (value << 1) | bit as u16;
+            self.pos += 1;
+        }
+        return value;
+    }
+}
+
+impl<'a> Iterator for AncPackets<'a> {
+    type Item = AncPacket;
+
+    fn next(&mut self) -> Option<AncPacket> {
+        // fixed fields up to and including data_count
+        let header_bits = 6 + 1 + 11 + 12 + 10 + 10 + 10;
+        let total_bits = self.data.len() * 8;
+        if self.pos + header_bits > total_bits {
+            return None;
+        }
+        if self.read(6) != 0 {
+            // stuffing, or not an anc_packet at all
+            self.pos = total_bits;
+            return None;
+        }
+        let c_not_y_channel = self.read(1) == 1;
+        let line_number = self.read(11);
+        let horizontal_offset = self.read(12);
+        let did = self.read(10) as u8;
+        let sdid = self.read(10) as u8;
+        let data_count = self.read(10) as u8;
+        if self.pos + (data_count as usize + 1) * 10 > total_bits {
+            self.data = &[];
+            self.pos = 0;
+            return None;
+        }
+        let user_data_words = (0..data_count).map(|_| self.read(10)).collect();
+        let checksum_word = self.read(10);
+        self.pos = self.pos.div_ceil(8) * 8;
+        return Some(AncPacket {
+            c_not_y_channel: c_not_y_channel,
+            line_number: line_number,
+            horizontal_offset: horizontal_offset,
+            did: did,
+            sdid: sdid,
+            data_count: data_count,
+            user_data_words: user_data_words,
+            checksum_word: checksum_word,
+        });
+    }
+}
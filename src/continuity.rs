@@ -0,0 +1,102 @@
+use crate::packet::{AdaptationField, Packet, PacketData, PacketHeader};
+use std::collections::BTreeMap;
+
+pub const NULL_PID: u16 = 0x1FFF;
+
+#[derive(Debug, PartialEq)]
+pub enum Continuity {
+    Ok,
+    // the spec allows a packet to be sent twice, same cc and identical bytes
+    Duplicate,
+    CcError { expected: u8, found: u8 },
+}
+
+struct PidState {
+    last: PacketData,
+    duplicated: bool,
+    // last is the packet a cc error resynced on, a repeat of it isn't counted again
+    resynced: bool,
+}
+
+// per PID loss figures, a gap is how many cc values were skipped, (found - expected) mod 16
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CcStats {
+    pub errors: u64,
+    pub max_gap: u8,
+    pub total_lost_estimate: u64,
+}
+
+#[derive(Default)]
+pub struct ContinuityChecker {
+    pids: BTreeMap<u16, PidState>,
+    stats: BTreeMap<u16, CcStats>,
+}
+
+impl ContinuityChecker {
+    pub fn new() -> ContinuityChecker {
+        return ContinuityChecker {
+            pids: BTreeMap::new(),
+            stats: BTreeMap::new(),
+        };
+    }
+
+    // only PIDs that have had a cc error show up
+    pub fn stats(&self) -> BTreeMap<u16, CcStats> {
+        return self.stats.clone();
+    }
+
+    pub fn check(&mut self, pkt: &Packet) -> Continuity {
+        let pid = pkt.pid();
+        if pid == NULL_PID {
+            // null packets have no meaningful cc so are never tracked
+            return Continuity::Ok;
+        }
+        let cc = pkt.cc();
+        let mut result = Continuity::Ok;
+        let mut duplicated = false;
+        let mut resynced = false;
+        if let Some(state) = self.pids.get(&pid) {
+            let last_cc = Packet::new(state.last).cc();
+            let discontinuity =
+                pkt.has_adaptation_field() && pkt.aflen() > 0 && pkt.is_discontinuity();
+            // cc only increments on packets carrying a payload
+            let expected = if pkt.has_payload() {
+                (last_cc + 1) & 0xF
+            } else {
+                last_cc
+            };
+            if !discontinuity && cc != expected {
+                let repeat = cc == last_cc && pkt.data() == &state.last;
+                if repeat && state.resynced {
+                    duplicated = true;
+                } else if repeat && !state.duplicated {
+                    result = Continuity::Duplicate;
+                    duplicated = true;
+                } else {
+                    let gap = cc.wrapping_sub(expected) & 0xF;
+                    let stats = self.stats.entry(pid).or_default();
+                    stats.errors += 1;
+                    stats.max_gap = stats.max_gap.max(gap);
+                    stats.total_lost_estimate += gap as u64;
+                    // the erroring packet is the new baseline, its cc is checked against
+                    // from here on but a repeat of it is neither a duplicate nor an error
+                    duplicated = true;
+                    resynced = true;
+                    result = Continuity::CcError {
+                        expected: expected,
+                        found: cc,
+                    };
+                }
+            }
+        }
+        self.pids.insert(
+            pid,
+            PidState {
+                last: *pkt.data(),
+                duplicated: duplicated,
+                resynced: resynced,
+            },
+        );
+        return result;
+    }
+}
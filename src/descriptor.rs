@@ -0,0 +1,154 @@
+use byteorder::{BigEndian, ByteOrder};
+
+pub const REGISTRATION_TAG: u8 = 0x05;
+pub const CA_TAG: u8 = 0x09;
+pub const ISO_639_LANGUAGE_TAG: u8 = 0x0A;
+pub const TELETEXT_TAG: u8 = 0x56;
+pub const SUBTITLING_TAG: u8 = 0x59;
+pub const DATA_BROADCAST_TAG: u8 = 0x64;
+pub const AC3_TAG: u8 = 0x6A;
+pub const APPLICATION_SIGNALLING_TAG: u8 = 0x6F;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Descriptor<'a> {
+    pub tag: u8,
+    pub data: &'a [u8],
+}
+
+impl<'a> Descriptor<'a> {
+    // the format_identifier of a registration descriptor, e.g. b"AC-3" or b"KLVA"
+    pub fn registration(&self) -> Option<&'a [u8]> {
+        if self.tag == REGISTRATION_TAG && self.data.len() >= 4 {
+            return Some(&self.data[0..4]);
+        }
+        return None;
+    }
+
+    // the first ISO 639 language code of a language, teletext or subtitling descriptor, all
+    // three of which open their loop with one
+    pub fn language(&self) -> Option<[u8; 3]> {
+        let tagged = self.tag == ISO_639_LANGUAGE_TAG
+            || self.tag == TELETEXT_TAG
+            || self.tag == SUBTITLING_TAG;
+        if tagged && self.data.len() >= 3 {
+            return Some([self.data[0], self.data[1], self.data[2]]);
+        }
+        return None;
+    }
+
+    // None unless this is a data_broadcast_descriptor with all of its fields in bounds
+    pub fn as_data_broadcast(&self) -> Option<DataBroadcastDescriptor<'a>> {
+        let data = self.data;
+        if self.tag != DATA_BROADCAST_TAG || data.len() < 4 {
+            return None;
+        }
+        let selector_end = 4 + data[3] as usize;
+        if data.len() < selector_end + 4 {
+            return None;
+        }
+        let text_end = selector_end + 4 + data[selector_end + 3] as usize;
+        if data.len() < text_end {
+            return None;
+        }
+        return Some(DataBroadcastDescriptor {
+            data_broadcast_id: BigEndian::read_u16(&data[0..2]),
+            component_tag: data[2],
+            selector: &data[4..selector_end],
+            language: [
+                data[selector_end],
+                data[selector_end + 1],
+                data[selector_end + 2],
+            ],
+            text: &data[selector_end + 4..text_end],
+        });
+    }
+
+    pub fn as_application_signalling(&self) -> Option<ApplicationSignallingDescriptor<'a>> {
+        if self.tag != APPLICATION_SIGNALLING_TAG {
+            return None;
+        }
+        return Some(ApplicationSignallingDescriptor { data: self.data });
+    }
+
+    pub fn ca_system_id(&self) -> Option<u16> {
+        if self.tag == CA_TAG && self.data.len() >= 4 {
+            return Some(BigEndian::read_u16(&self.data[0..2]));
+        }
+        return None;
+    }
+
+    // the CA_PID of a CA_descriptor, the EMM PID when found in the CAT and the ECM PID when
+    // found in a PMT
+    pub fn ca_pid(&self) -> Option<u16> {
+        if self.tag == CA_TAG && self.data.len() >= 4 {
+            return Some(0x1FFF & BigEndian::read_u16(&self.data[2..4]));
+        }
+        return None;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DataBroadcastDescriptor<'a> {
+    pub data_broadcast_id: u16,
+    pub component_tag: u8,
+    pub selector: &'a [u8],
+    pub language: [u8; 3],
+    pub text: &'a [u8],
+}
+
+// the application_type/AIT_version pairs of an application_signalling_descriptor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ApplicationSignallingDescriptor<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> ApplicationSignallingDescriptor<'a> {
+    // (application_type, AIT_version_number), with the reserved bits dropped
+    pub fn applications(&self) -> Vec<(u16, u8)> {
+        return self
+            .data
+            .chunks_exact(3)
+            .map(|entry| (0x7FFF & BigEndian::read_u16(&entry[0..2]), entry[2] & 0x1F))
+            .collect();
+    }
+}
+
+// a CA_descriptor with no private data bytes, ca_pid being an ECM PID for a PMT or an EMM
+// PID for the CAT
+pub fn ca_descriptor(ca_system_id: u16, ca_pid: u16) -> [u8; 6] {
+    let mut desc = [CA_TAG, 4, 0, 0, 0, 0];
+    BigEndian::write_u16(&mut desc[2..4], ca_system_id);
+    BigEndian::write_u16(&mut desc[4..6], 0xE000 | ca_pid);
+    return desc;
+}
+
+pub struct DescriptorIter<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for DescriptorIter<'a> {
+    type Item = Descriptor<'a>;
+
+    fn next(&mut self) -> Option<Descriptor<'a>> {
+        if self.data.len() < 2 {
+            return None;
+        }
+        let len = self.data[1] as usize;
+        if self.data.len() < 2 + len {
+            // truncated descriptor, stop rather than read past the loop
+            self.data = &[];
+            return None;
+        }
+        let desc = Descriptor {
+            tag: self.data[0],
+            data: &self.data[2..2 + len],
+        };
+        self.data = &self.data[2 + len..];
+        return Some(desc);
+    }
+}
+
+// walks a descriptor loop such as a PMT's program info or an elementary stream's es info
+pub fn descriptors(data: &[u8]) -> DescriptorIter<'_> {
+    return DescriptorIter { data: data };
+}
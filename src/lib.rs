@@ -1,14 +1,93 @@
+#[cfg(feature = "tokio")]
+pub mod async_reader;
+pub mod codec;
+pub mod continuity;
+pub mod descriptor;
 pub mod packet;
+pub mod pes;
 pub mod psi;
+pub mod rate;
+pub mod scte35;
+pub mod synth;
+pub mod time;
+pub mod validate;
 
 #[cfg(test)]
 mod tests {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::collections::{BTreeMap, BTreeSet};
 
-    use crate::packet::{AdaptationField, Packet, PacketHeader, Payload};
+    use byteorder::{BigEndian, ByteOrder};
+    use std::convert::TryInto;
+
+    use crate::codec::{anc_packets, detect_aac_framing, AacFraming, AncPacket};
+    use crate::continuity::{CcStats, Continuity, ContinuityChecker};
+    use crate::descriptor::{descriptors, Descriptor};
+    use crate::packet::{
+        build_index, detect_packet_size, find_start_codes, for_each_packet, packet_diff,
+        parse_header, pid_set, resync, stuffing_af_for, stuffing_only_af, try_for_each_packet,
+        AdaptationField, EsPriorityCollector, EsPriorityCounts, HeaderFields, NullStripper, Packet,
+        PacketDiff, PacketError, PacketHeader, PacketReader, PacketRef, Payload, ReadEvent,
+        RingDemux, ScramblingControl, TsWriter,
+    };
+    use crate::pes::{
+        check_alignment, extract_es, packetize_pes, write_timestamp, PesAssembler, PesError,
+        PesHeader, PesScramblingControl, SeekIndexBuilder, SeekPoint, TimestampOrderChecker,
+        TimestampViolation, TrickMode,
+    };
     use crate::psi::{
-        calc_crc32, create_pat_packet, create_pmt_packet, ElementaryStream, TableHeader,
-        TableSyntaxSection, PAT, PMT, PSI,
+        calc_crc32, create_eit_packet, create_pat_packet, create_pat_packet_with_programs,
+        create_pmt_packet, create_pmt_packet_with_ca, create_pmt_packet_with_descriptors,
+        describe_stream, detect_pcr_pid, elementary_streams_bounded, find_pat, parse_eit_events,
+        pmt_content_eq, program_count, resolve_private_stream, sections, segment_table,
+        set_es_info_len, set_program_info_len, table_ids_by_pid, try_calc_crc32,
+        try_create_pmt_packet, verify_section_length, CachedValidator, EitError, EitKind,
+        ElementaryStream, EventSpec, PatEntry, PmtEditor, PmtError, PmtPresenceChecker,
+        PrivateSection, PrivateStreamKind, ProgramExtractor, ProgramMap, SdtBatCollector,
+        SdtBatTable, SectionAssembler, SectionEditor, SectionError, StreamType, TableCollector,
+        TableHeader, TableSyntaxSection, TableUpdater, CAT_PID, EIT, EIT_PID, PAT, PMT, PSI,
+        SDT_BAT_PID,
+    };
+    use crate::rate::{
+        min_bitrate_for_pcr, nominal_bitrate, packets_between_pcrs, packets_per_second,
+        pcr_packets_per_second, pid_stats, BitrateEstimator, PcrScheduler,
     };
+    use crate::scte35::parse_splice_insert;
+    use crate::synth::create_two_program_stream;
+    use crate::time::{
+        arrival_delta, arrival_jitter, gop_stats, pcr_diff, pcr_from_pts, pts_from_pcr,
+        PcrAnalyzer, PcrOffender, PtsInterpolator, TimelineSplicer, WithPcrExt, PCR_WRAP, PTS_WRAP,
+    };
+    use crate::validate::{roundtrip_ok, validate_stream};
+
+    // counts allocations made on the current thread so tests can check a path doesn't allocate
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            return System.alloc(layout);
+        }
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout);
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn allocation_count() -> usize {
+        return ALLOCATIONS.with(|count| count.get());
+    }
+
+    const SAMPLE_PAT_HEX: &str = "474000100000B00D0001C100000001F0002AB104B2FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF";
+    const SAMPLE_PMT_HEX: &str = "475000100002B0120001C10000E100F0001BE100F00015BD4D56FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF";
+    const SAMPLE_PAYLOAD_HEX: &str = "47410030075000007B0C7E00000001E0000080C00A31000912F9110007D8610000000109F00000000167F40028919B280F0044FC4E0220000003002000000601E30632C00000000168EBE3C9200000010605FFFFAADC45E9BDE6D948B7962CD820D923EEEF78323634202D20636F7265203135352072323931372030613834643938202D20482E3236342F4D5045472D342041564320636F646563202D20436F70796C65667420323030332D32303138202D20687474703A2F2F7777";
 
     fn hex_to_bin<T: AsRef<[u8]>>(hex: T) -> [u8; 188] {
         let mut pat_data_bin = [0u8; 188];
@@ -16,9 +95,145 @@ mod tests {
         return pat_data_bin;
     }
 
+    // wraps a single section starting right after a zero pointer field
+    fn psi_packet(pid: u16, section: &[u8], cc: u8) -> [u8; 188] {
+        let mut pkt = Packet::create_packet(false, true, false, pid, 0, 1, cc);
+        pkt[4] = 0;
+        pkt[5..5 + section.len()].copy_from_slice(section);
+        return pkt;
+    }
+
+    // long form section, version 0 and current, with the CRC filled in
+    fn long_section(
+        table_id: u8,
+        table_id_ext: u16,
+        section_num: u8,
+        last_section_num: u8,
+        body: &[u8],
+    ) -> Vec<u8> {
+        let section_len = 9 + body.len();
+        let mut section = vec![
+            table_id,
+            0xB0 | (section_len >> 8) as u8,
+            section_len as u8,
+            (table_id_ext >> 8) as u8,
+            table_id_ext as u8,
+            0xC1,
+            section_num,
+            last_section_num,
+        ];
+        section.extend_from_slice(body);
+        section.extend_from_slice(&[0; 4]);
+        let crc = calc_crc32(&section);
+        let crc_idx = section.len() - 4;
+        BigEndian::write_u32(&mut section[crc_idx..], crc);
+        return section;
+    }
+
+    fn pat_section(section_num: u8, last_section_num: u8, programs: &[(u16, u16)]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (num, pid) in programs.iter() {
+            body.extend_from_slice(&[(num >> 8) as u8, *num as u8]);
+            body.extend_from_slice(&[0xE0 | (pid >> 8) as u8, *pid as u8]);
+        }
+        return long_section(0, 1, section_num, last_section_num, &body);
+    }
+
+    // PAT, the first packet of a two packet PMT on 0x1000, the PAT again then the rest of
+    // the PMT, so the PMT section is still being put together when the second PAT goes by
+    fn interleaved_pmt_capture() -> Vec<u8> {
+        let pat = pat_section(0, 0, &[(1, 0x1000)]);
+        let mut program_info = Vec::new();
+        for _ in 0..40 {
+            program_info.extend_from_slice(&[0x05, 4, b'K', b'L', b'V', b'A']);
+        }
+        let streams: [(u16, u8, &[u8]); 1] = [(256, 0x1B, &[])];
+        let pmt_packets =
+            create_pmt_packet_with_descriptors(0x1000, 1, 256, &program_info, &streams, 0);
+        assert_eq!(pmt_packets.len(), 2);
+        let mut capture = Vec::new();
+        capture.extend_from_slice(&psi_packet(0, &pat, 0));
+        capture.extend_from_slice(&pmt_packets[0]);
+        capture.extend_from_slice(&psi_packet(0, &pat, 1));
+        capture.extend_from_slice(&pmt_packets[1]);
+        return capture;
+    }
+
+    // packet on PID 256 carrying just the adaptation field body (flags onwards) given
+    fn af_packet(af_body: &[u8]) -> Packet {
+        let mut pkt = Packet::create_packet(false, false, false, 256, 0, 3, 0);
+        pkt[4] = af_body.len() as u8;
+        pkt[5..5 + af_body.len()].copy_from_slice(af_body);
+        return Packet::new(pkt);
+    }
+
+    fn pcr_bytes(base: u64, ext: u16) -> [u8; 6] {
+        return [
+            (base >> 25) as u8,
+            (base >> 17) as u8,
+            (base >> 9) as u8,
+            (base >> 1) as u8,
+            (((base & 1) << 7) as u8) | 0x7E | (ext >> 8) as u8,
+            ext as u8,
+        ];
+    }
+
+    // splits a PES over packets on pid, the last one padded with a stuffing adaptation field
+    fn pes_packets(pid: u16, pes: &[u8], first_cc: u8) -> Vec<Packet> {
+        return packetize_pes(pid, pes, first_cc)
+            .into_iter()
+            .map(Packet::new)
+            .collect();
+    }
+
+    // a PAT built from pids passes roundtrip_ok and gives back programs 1.. pointing at them
+    fn assert_roundtrip_pat(pids: &[u16]) {
+        let pat = create_pat_packet(pids, 0);
+        assert_eq!(roundtrip_ok(&pat), true);
+        let pkt = Packet::new(pat);
+        let tables = pkt.tables().unwrap();
+        let mut parsed = Vec::new();
+        let mut pos = 0;
+        while pos < tables.len() && tables[pos] != 0xFF {
+            let current = &tables[pos..];
+            let section = current.section_data();
+            let pat = section.table_data();
+            parsed.push((pat.program_num(), pat.program_map_pid()));
+            pos += section.len();
+        }
+        let expected: Vec<(u16, u16)> = (1..).zip(pids.iter().cloned()).collect();
+        assert_eq!(parsed, expected);
+    }
+
+    // a PMT built from (pid, stream_type) pairs passes roundtrip_ok and lists them back
+    fn assert_roundtrip_pmt(pmt_pid: u16, pid_type_pairs: &[(u16, u8)]) {
+        let pmt = create_pmt_packet(pmt_pid, pid_type_pairs, 0);
+        assert_eq!(roundtrip_ok(&pmt), true);
+        let pkt = Packet::new(pmt);
+        assert_eq!(pkt.pid(), pmt_pid);
+        let tables = pkt.tables().unwrap();
+        let section = tables.section_data();
+        let parsed: Vec<(u16, u8)> = section
+            .table_data()
+            .elementary_streams()
+            .chunks_exact(5)
+            .map(|entry| (entry.stream_pid(), entry.stream_type()))
+            .collect();
+        assert_eq!(parsed, pid_type_pairs);
+    }
+
+    // PAT, PMT then the PID 256 payload packet back to back like a capture would have them
+    fn sample_capture() -> Vec<u8> {
+        let mut capture = Vec::new();
+        capture.extend_from_slice(&hex_to_bin(SAMPLE_PAT_HEX));
+        capture.extend_from_slice(&hex_to_bin(SAMPLE_PMT_HEX));
+        capture.extend_from_slice(&hex_to_bin(SAMPLE_PAYLOAD_HEX));
+        return capture;
+    }
+
     #[test]
     fn validate_pat() {
-        let pat_data_hex = "474000100000B00D0001C100000001F0002AB104B2FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF";
+        let pat_data_hex = SAMPLE_PAT_HEX;
         let pat_data_bin = hex_to_bin(pat_data_hex);
         let pat_pkt = Packet::new(pat_data_bin);
         assert_eq!(pat_pkt.sync(), 0x47);
@@ -42,7 +257,7 @@ mod tests {
 
     #[test]
     fn validate_pmt() {
-        let pmt_data_hex = "475000100002B0120001C10000E100F0001BE100F00015BD4D56FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF";
+        let pmt_data_hex = SAMPLE_PMT_HEX;
         let pmt_pkt = Packet::new(hex_to_bin(pmt_data_hex));
         assert_eq!(pmt_pkt.sync(), 0x47);
         assert_eq!(pmt_pkt.pid(), 0x1000);
@@ -67,7 +282,7 @@ mod tests {
     }
     #[test]
     fn validate_payload() {
-        let payload_data_hex = "47410030075000007B0C7E00000001E0000080C00A31000912F9110007D8610000000109F00000000167F40028919B280F0044FC4E0220000003002000000601E30632C00000000168EBE3C9200000010605FFFFAADC45E9BDE6D948B7962CD820D923EEEF78323634202D20636F7265203135352072323931372030613834643938202D20482E3236342F4D5045472D342041564320636F646563202D20436F70796C65667420323030332D32303138202D20687474703A2F2F7777";
+        let payload_data_hex = SAMPLE_PAYLOAD_HEX;
         let payload_pkt = Packet::new(hex_to_bin(payload_data_hex));
         assert_eq!(payload_pkt.sync(), 0x47);
         assert_eq!(payload_pkt.pid(), 0x100);
@@ -102,8 +317,10 @@ mod tests {
 
     #[test]
     fn synth_packet_with_large_payload() {
-        let large_payload: [u8;184] = [0xBB; 184];
-        let raw_pkt = Packet::create_packet_with_payload(false, true, false, 0, 0, 1, 9, &large_payload);
+        let large_payload: [u8; 184] = [0xBB; 184];
+        let raw_pkt =
+            Packet::create_packet_with_payload(false, true, false, 0, 0, 1, 9, &large_payload)
+                .unwrap();
         println!("raw {}", hex::encode_upper(&raw_pkt[..]));
         let synth_pkt = Packet::new(raw_pkt);
         assert_eq!(synth_pkt.sync(), 0x47);
@@ -121,7 +338,9 @@ mod tests {
     #[test]
     fn synth_packet_with_small_payload() {
         let small_payload: [u8; 8] = [0xDE, 0xAD, 0xBE, 0xEF, 0xCA, 0xFE, 0xBA, 0xBE];
-        let raw_pkt = Packet::create_packet_with_payload(false, true, false, 0, 0, 1, 9, &small_payload); 
+        let raw_pkt =
+            Packet::create_packet_with_payload(false, true, false, 0, 0, 3, 9, &small_payload)
+                .unwrap();
         let synth_pkt = Packet::new(raw_pkt);
         println!("raw {}", hex::encode_upper(&raw_pkt[..]));
         assert_eq!(synth_pkt.sync(), 0x47);
@@ -140,6 +359,2970 @@ mod tests {
         assert_eq!(updated_pkt.aflen(), 175);
     }
 
+    #[test]
+    fn synth_packet_afc_mismatch() {
+        let small_payload: [u8; 8] = [0xDE, 0xAD, 0xBE, 0xEF, 0xCA, 0xFE, 0xBA, 0xBE];
+        assert_eq!(
+            Packet::create_packet_with_payload(false, true, false, 0, 0, 1, 9, &small_payload),
+            Err(PacketError::AfcMismatch {
+                requested: 1,
+                required: 3
+            })
+        );
+        let large_payload: [u8; 184] = [0xBB; 184];
+        assert_eq!(
+            Packet::create_packet_with_payload(false, true, false, 0, 0, 3, 9, &large_payload),
+            Err(PacketError::AfcMismatch {
+                requested: 3,
+                required: 1
+            })
+        );
+        let small_pkt = Packet::new(
+            Packet::create_packet_with_payload(false, true, false, 0, 0, 3, 9, &small_payload)
+                .unwrap(),
+        );
+        assert_eq!(small_pkt.afc(), 3);
+        let large_pkt = Packet::new(
+            Packet::create_packet_with_payload(false, true, false, 0, 0, 1, 9, &large_payload)
+                .unwrap(),
+        );
+        assert_eq!(large_pkt.afc(), 1);
+    }
+
+    #[test]
+    fn stuffing_af_for_short_payload() {
+        let (afc, af) = stuffing_af_for(8);
+        assert_eq!(afc, 3);
+        assert_eq!(af.len(), 176);
+        assert_eq!(af[0], 175);
+        assert_eq!(af[1], 0);
+        assert_eq!(af[2..].iter().all(|b| *b == 0xFF), true);
+        assert_eq!(stuffing_af_for(184), (1, Vec::new()));
+        assert_eq!(stuffing_af_for(183), (3, vec![0]));
+
+        // no payload at all is adaptation field only
+        let (afc, af) = stuffing_af_for(0);
+        assert_eq!(afc, 2);
+        assert_eq!(af.len(), 184);
+        assert_eq!(af[0], 183);
+        let data = Packet::create_packet_with_payload(false, false, false, 256, 0, 2, 0, &[]);
+        assert_eq!(Packet::new(data.unwrap()).has_payload(), false);
+        assert_eq!(
+            Packet::create_packet_with_payload(false, false, false, 256, 0, 3, 0, &[]),
+            Err(PacketError::AfcMismatch {
+                requested: 3,
+                required: 2
+            })
+        );
+    }
+
+    #[test]
+    fn index_sample_capture() {
+        let capture = sample_capture();
+        let index = build_index(&capture);
+        assert_eq!(index.len(), capture.len() / 188);
+        assert_eq!(index[0].offset, 0);
+        assert_eq!(index[0].pid, 0);
+        assert_eq!(index[0].pusi, true);
+        assert_eq!(index[1].offset, 188);
+        assert_eq!(index[1].pid, 0x1000);
+        assert_eq!(index[2].offset, 376);
+        assert_eq!(index[2].pid, 0x100);
+        for entry in index.iter() {
+            assert_eq!(entry.pcr.is_some(), entry.pid == 0x100);
+        }
+    }
+
+    #[test]
+    fn continuity_legal_duplicate() {
+        let payload = [0xAB; 184];
+        let first = Packet::create_packet_with_payload(false, false, false, 256, 0, 1, 4, &payload)
+            .unwrap();
+        let second =
+            Packet::create_packet_with_payload(false, false, false, 256, 0, 1, 5, &payload)
+                .unwrap();
+        let mut checker = ContinuityChecker::new();
+        assert_eq!(checker.check(&Packet::new(first)), Continuity::Ok);
+        assert_eq!(checker.check(&Packet::new(second)), Continuity::Ok);
+        assert_eq!(checker.check(&Packet::new(second)), Continuity::Duplicate);
+        // only a single duplicate is allowed
+        assert_eq!(
+            checker.check(&Packet::new(second)),
+            Continuity::CcError {
+                expected: 6,
+                found: 5
+            }
+        );
+    }
+
+    #[test]
+    fn continuity_after_cc_error() {
+        let first =
+            Packet::create_packet_with_payload(false, false, false, 256, 0, 1, 4, &[0xAB; 184])
+                .unwrap();
+        let jumped =
+            Packet::create_packet_with_payload(false, false, false, 256, 0, 1, 9, &[0xCD; 184])
+                .unwrap();
+        let next =
+            Packet::create_packet_with_payload(false, false, false, 256, 0, 1, 10, &[0xEF; 184])
+                .unwrap();
+        let mut checker = ContinuityChecker::new();
+        assert_eq!(checker.check(&Packet::new(first)), Continuity::Ok);
+        assert_eq!(
+            checker.check(&Packet::new(jumped)),
+            Continuity::CcError {
+                expected: 5,
+                found: 9
+            }
+        );
+        // the erroring packet is the new baseline, a repeat of it isn't a duplicate
+        assert_eq!(checker.check(&Packet::new(jumped)), Continuity::Ok);
+        assert_eq!(checker.check(&Packet::new(next)), Continuity::Ok);
+        assert_eq!(checker.stats()[&256].errors, 1);
+
+        // a second bad cc straight after an error is still caught
+        let mut checker = ContinuityChecker::new();
+        let wrong =
+            Packet::create_packet_with_payload(false, false, false, 256, 0, 1, 2, &[0x12; 184])
+                .unwrap();
+        assert_eq!(checker.check(&Packet::new(first)), Continuity::Ok);
+        assert_eq!(
+            checker.check(&Packet::new(jumped)),
+            Continuity::CcError {
+                expected: 5,
+                found: 9
+            }
+        );
+        assert_eq!(
+            checker.check(&Packet::new(wrong)),
+            Continuity::CcError {
+                expected: 10,
+                found: 2
+            }
+        );
+        assert_eq!(checker.stats()[&256].errors, 2);
+    }
+
+    #[test]
+    fn continuity_illegal_repeat() {
+        let first =
+            Packet::create_packet_with_payload(false, false, false, 256, 0, 1, 4, &[0xAB; 184])
+                .unwrap();
+        let repeat =
+            Packet::create_packet_with_payload(false, false, false, 256, 0, 1, 4, &[0xCD; 184])
+                .unwrap();
+        let mut checker = ContinuityChecker::new();
+        assert_eq!(checker.check(&Packet::new(first)), Continuity::Ok);
+        assert_eq!(
+            checker.check(&Packet::new(repeat)),
+            Continuity::CcError {
+                expected: 5,
+                found: 4
+            }
+        );
+    }
+
+    #[test]
+    fn header_only_parse() {
+        let sample = hex_to_bin(SAMPLE_PAYLOAD_HEX);
+        let header = parse_header(&sample[..]);
+        let pkt = Packet::new(sample);
+        assert_eq!(header.pid(), pkt.pid());
+        assert_eq!(header.pusi(), pkt.pusi());
+        assert_eq!(header.cc(), pkt.cc());
+        assert_eq!(header.afc(), pkt.afc());
+    }
+
+    #[test]
+    fn find_sample_pat() {
+        assert_eq!(find_pat(&sample_capture()), Some(vec![(1, 0x1000)]));
+        assert_eq!(find_pat(&hex_to_bin(SAMPLE_PMT_HEX)), None);
+    }
+
+    #[test]
+    fn private_stream_registration() {
+        let ac3_es: &[u8] = &[
+            0x06, 0xE1, 0x01, 0xF0, 0x06, 0x05, 0x04, b'A', b'C', b'-', b'3',
+        ];
+        assert_eq!(ac3_es.valid_stream(), true);
+        assert_eq!(ac3_es.stream_type(), 0x06);
+        assert_eq!(ac3_es.stream_type_enum(), StreamType::Ac3);
+        let klv_es: &[u8] = &[
+            0x06, 0xE1, 0x02, 0xF0, 0x06, 0x05, 0x04, b'K', b'L', b'V', b'A',
+        ];
+        assert_eq!(klv_es.stream_type_enum(), StreamType::Klva);
+        let bare_private_es: &[u8] = &[0x06, 0xE1, 0x03, 0xF0, 0x00];
+        assert_eq!(bare_private_es.stream_type_enum(), StreamType::PrivatePes);
+        let atsc_ac3_es: &[u8] = &[0x81, 0xE1, 0x04, 0xF0, 0x00];
+        assert_eq!(atsc_ac3_es.stream_type_enum(), StreamType::Ac3);
+        let eac3_es: &[u8] = &[0x87, 0xE1, 0x05, 0xF0, 0x00];
+        assert_eq!(eac3_es.stream_type_enum(), StreamType::EAc3);
+        let scte35_es: &[u8] = &[0x86, 0xE1, 0x06, 0xF0, 0x00];
+        assert_eq!(scte35_es.stream_type_enum(), StreamType::Scte35);
+    }
+
+    #[test]
+    fn program_map_psi_pids() {
+        let mut map = ProgramMap::from_pat(&find_pat(&sample_capture()).unwrap());
+        assert_eq!(map.pmt_pid(1), Some(0x1000));
+        assert_eq!(map.is_psi_pid(0), true);
+        assert_eq!(map.is_psi_pid(0x1000), true);
+        assert_eq!(map.is_psi_pid(0x100), false);
+        map.add_psi_pid(0x11);
+        assert_eq!(map.is_psi_pid(0x11), true);
+    }
+
+    #[test]
+    fn pmt_presence_missing_pid() {
+        // program 2's PMT never makes it into the stream
+        let mut stream = Vec::new();
+        for window in create_two_program_stream().chunks_exact(188) {
+            if Packet::new(window.try_into().unwrap()).pid() != 0x1100 {
+                stream.extend_from_slice(window);
+            }
+        }
+        let map = ProgramMap::from_pat(&find_pat(&stream).unwrap());
+        let window = (stream.len() / 188) as u64;
+        let mut checker = PmtPresenceChecker::new(&map, window);
+        for_each_packet(&stream[..188 * 5], |pkt| checker.push(pkt));
+        assert_eq!(checker.missing(), Vec::<u16>::new());
+        for_each_packet(&stream[188 * 5..], |pkt| checker.push(pkt));
+        assert_eq!(checker.missing(), vec![0x1100]);
+        // program 1's PMT, the second packet, going quiet for a whole window is flagged too
+        for_each_packet(&stream[188 * 2..188 * 4], |pkt| checker.push(pkt));
+        assert_eq!(checker.missing(), vec![0x1000, 0x1100]);
+    }
+
+    #[test]
+    fn find_multi_section_pat() {
+        let mut capture = Vec::new();
+        capture.extend_from_slice(&psi_packet(
+            0,
+            &pat_section(0, 1, &[(1, 0x100), (2, 0x200)]),
+            0,
+        ));
+        capture.extend_from_slice(&hex_to_bin(SAMPLE_PMT_HEX));
+        capture.extend_from_slice(&psi_packet(0, &pat_section(1, 1, &[(3, 0x300)]), 1));
+        assert_eq!(
+            find_pat(&capture),
+            Some(vec![(1, 0x100), (2, 0x200), (3, 0x300)])
+        );
+        // without the second section the PAT isn't complete
+        assert_eq!(find_pat(&capture[..376]), None);
+    }
+
+    #[test]
+    fn find_pat_section_across_packets() {
+        let mut capture = Vec::new();
+        capture.extend_from_slice(&psi_packet(0, &pat_section(0, 1, &[(1, 0x100)]), 0));
+        // 50 programs take the second section past what one packet holds
+        let programs: Vec<(u16, u16)> = (0..50).map(|n| (2 + n, 0x200 + n)).collect();
+        let section = pat_section(1, 1, &programs);
+        assert_eq!(section.len() > 183, true);
+        capture.extend_from_slice(&psi_packet(0, &section[..183], 1));
+        let mut rest = Packet::create_packet(false, false, false, 0, 0, 1, 2);
+        rest[4..4 + section.len() - 183].copy_from_slice(&section[183..]);
+        rest[4 + section.len() - 183..].fill(0xFF);
+        capture.extend_from_slice(&rest);
+
+        let mut expected = vec![(1, 0x100)];
+        expected.extend_from_slice(&programs);
+        assert_eq!(find_pat(&capture), Some(expected));
+        assert_eq!(find_pat(&capture[..376]), None);
+    }
+
+    #[test]
+    fn continuity_ignores_null_packets() {
+        let first =
+            Packet::create_packet_with_payload(false, false, false, 256, 0, 1, 4, &[0xAB; 184])
+                .unwrap();
+        let second =
+            Packet::create_packet_with_payload(false, false, false, 256, 0, 1, 5, &[0xAB; 184])
+                .unwrap();
+        let null_a = Packet::create_packet(false, false, false, 0x1FFF, 0, 1, 0);
+        let null_b = Packet::create_packet(false, false, false, 0x1FFF, 0, 1, 9);
+        let mut checker = ContinuityChecker::new();
+        assert_eq!(checker.check(&Packet::new(first)), Continuity::Ok);
+        assert_eq!(checker.check(&Packet::new(null_a)), Continuity::Ok);
+        assert_eq!(checker.check(&Packet::new(null_a)), Continuity::Ok);
+        assert_eq!(checker.check(&Packet::new(null_b)), Continuity::Ok);
+        assert_eq!(checker.check(&Packet::new(second)), Continuity::Ok);
+    }
+
+    #[test]
+    fn pes_extension_pstd_buffer() {
+        let pes: &[u8] = &[
+            0x00, 0x00, 0x01, 0xE0, 0x00, 0x00, 0x80, 0x01, 0x03, 0x10, 0x61, 0x23,
+        ];
+        assert_eq!(pes.valid_start_code(), true);
+        assert_eq!(pes.has_optional_header(), true);
+        assert_eq!(pes.has_extension(), true);
+        assert_eq!(pes.private_data(), None);
+        assert_eq!(pes.pstd_buffer(), Some((true, 0x123)));
+
+        // PTS ahead of the extension and private data ahead of the P-STD buffer
+        let mut pes_with_private = vec![
+            0x00, 0x00, 0x01, 0xE0, 0x00, 0x00, 0x80, 0x81, 0x18, 0x21, 0x00, 0x01, 0x00, 0x01,
+            0x90,
+        ];
+        pes_with_private.extend_from_slice(&[0xAA; 16]);
+        pes_with_private.extend_from_slice(&[0x40, 0x40]);
+        let pes = pes_with_private.as_slice();
+        assert_eq!(pes.private_data(), Some(&[0xAA; 16]));
+        assert_eq!(pes.pstd_buffer(), Some((false, 0x40)));
+
+        // cut off part way through the private data, then before the extension flags
+        let truncated = &pes_with_private[..20];
+        assert_eq!(truncated.has_private_data(), true);
+        assert_eq!(truncated.private_data(), None);
+        assert_eq!(truncated.pstd_buffer(), None);
+        let truncated = &pes_with_private[..14];
+        assert_eq!(truncated.has_extension(), true);
+        assert_eq!(truncated.has_private_data(), false);
+        assert_eq!(truncated.has_pstd_buffer(), false);
+        assert_eq!(truncated.has_extension_2(), false);
+        assert_eq!(truncated.private_data(), None);
+        assert_eq!(truncated.pstd_buffer(), None);
+
+        // a pack header whose length byte never arrived
+        let pack: &[u8] = &[0x00, 0x00, 0x01, 0xE0, 0x00, 0x00, 0x80, 0x01, 0x03, 0x50];
+        assert_eq!(pack.has_pack_header(), true);
+        assert_eq!(pack.pstd_buffer(), None);
+        // and no optional header to hold an extension at all
+        let no_header: &[u8] = &[0x00, 0x00, 0x01, 0xBE, 0x00, 0x00, 0xFF, 0xFF, 0xFF];
+        assert_eq!(no_header.has_private_data(), false);
+        assert_eq!(no_header.pstd_buffer(), None);
+    }
+
+    #[test]
+    fn pcr_rate_planning_40ms() {
+        assert_eq!(min_bitrate_for_pcr(40), 37_600);
+        assert_eq!(pcr_packets_per_second(4_000_000, 40), 25);
+        // too low a bitrate to carry 25 packets a second
+        assert_eq!(pcr_packets_per_second(15_040, 40), 10);
+        assert_eq!(packets_per_second(3_760_000), 2500);
+        assert_eq!(packets_between_pcrs(3_760_000, 40), 100);
+    }
+
+    #[test]
+    fn crc_of_short_section() {
+        assert_eq!(try_calc_crc32(&[0; 2]), None);
+        let pat_pkt = Packet::new(hex_to_bin(SAMPLE_PAT_HEX));
+        let tables = pat_pkt.tables().unwrap();
+        let pat_section = tables.section_data();
+        assert_eq!(try_calc_crc32(pat_section), Some(pat_section.crc32()));
+        assert_eq!(calc_crc32(&[0; 2]), 0);
+    }
+
+    #[test]
+    fn start_code_across_packets() {
+        let mut first_payload = [0xAB; 184];
+        first_payload[182] = 0;
+        first_payload[183] = 0;
+        let mut second_payload = [0xCD; 184];
+        second_payload[0] = 0x01;
+        second_payload[10..14].copy_from_slice(&[0x00, 0x00, 0x01, 0xE0]);
+        let mut capture = Vec::new();
+        capture.extend_from_slice(
+            &Packet::create_packet_with_payload(false, true, false, 0x100, 0, 1, 0, &first_payload)
+                .unwrap(),
+        );
+        // a packet on another PID in between mustn't break up the carried bytes
+        capture.extend_from_slice(&hex_to_bin(SAMPLE_PAT_HEX));
+        capture.extend_from_slice(
+            &Packet::create_packet_with_payload(
+                false,
+                false,
+                false,
+                0x100,
+                0,
+                1,
+                1,
+                &second_payload,
+            )
+            .unwrap(),
+        );
+        assert_eq!(find_start_codes(&capture, 0x100), vec![(0, 182), (2, 10)]);
+
+        // the sample payload's PES header comes right after its adaptation field
+        let sample = hex_to_bin(SAMPLE_PAYLOAD_HEX);
+        assert_eq!(find_start_codes(&sample, 0x100)[0], (0, 0));
+    }
+
+    #[test]
+    fn clear_scrambled_packet() {
+        let scrambled = Packet::create_packet(false, false, false, 0x100, 3, 3, 7);
+        assert_eq!(Packet::new(scrambled).tsc(), 3);
+        let cleared = Packet::new(Packet::clear_scrambling(scrambled));
+        assert_eq!(cleared.tsc(), 0);
+        assert_eq!(cleared.afc(), 3);
+        assert_eq!(cleared.cc(), 7);
+        assert_eq!(cleared.pid(), 0x100);
+    }
+
+    #[test]
+    fn extract_sample_es() {
+        let es = extract_es(&sample_capture(), 0x100);
+        assert_eq!(&es[0..4], &[0x00, 0x00, 0x00, 0x01]);
+        // the access unit delimiter follows the PES header
+        assert_eq!(es[4], 0x09);
+        assert_eq!(extract_es(&sample_capture(), 0x101), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn extract_es_drops_unit_with_lost_packet() {
+        let unit = |len: usize, fill: u8| {
+            let mut pes = vec![0x00, 0x00, 0x01, 0xE0, 0x00, 0x00, 0x80, 0x00, 0x00];
+            pes.resize(len, fill);
+            return pes;
+        };
+        let first = unit(300, 0xA1);
+        let second = unit(500, 0xB2);
+        let third = unit(250, 0xC3);
+        let mut packets = pes_packets(0x100, &first, 0);
+        let mut lossy = pes_packets(0x100, &second, 2);
+        // the middle packet of the second unit never arrives
+        lossy.remove(1);
+        packets.extend(lossy);
+        packets.extend(pes_packets(0x100, &third, 5));
+        let mut capture = Vec::new();
+        for pkt in packets.iter() {
+            capture.extend_from_slice(pkt.data());
+        }
+
+        let mut expected = first[9..].to_vec();
+        expected.extend_from_slice(&third[9..]);
+        assert_eq!(extract_es(&capture, 0x100), expected);
+    }
+
+    #[test]
+    fn sample_pcr() {
+        let pkt = Packet::new(hex_to_bin(SAMPLE_PAYLOAD_HEX));
+        assert_eq!(pkt.is_random_access(), true);
+        assert_eq!(pkt.pcr(), 63000 * 300);
+        assert_eq!(pkt.pcr_nanos(), 700_000_000);
+        assert_eq!(pkt.has_opcr(), false);
+        assert_eq!(pkt.opcr(), 0);
+    }
+
+    #[test]
+    fn af_private_data_and_extension() {
+        let private = [0xDE, 0xAD, 0xBE, 0xEF];
+        let extension = [0x1F, 0xAB];
+
+        // private data and extension only
+        let mut body = vec![0x03, 4];
+        body.extend_from_slice(&private);
+        body.push(2);
+        body.extend_from_slice(&extension);
+        let pkt = af_packet(&body);
+        assert_eq!(pkt.has_pcr(), false);
+        assert_eq!(pkt.transport_private_data_len(), 4);
+        assert_eq!(pkt.transport_private_data(), &private);
+        assert_eq!(pkt.extension(), &extension);
+
+        // pcr and splice countdown ahead of them
+        let mut body = vec![0x17];
+        body.extend_from_slice(&pcr_bytes(0x1_2345_6789, 0x123));
+        body.extend_from_slice(&[0x05, 4]);
+        body.extend_from_slice(&private);
+        body.push(2);
+        body.extend_from_slice(&extension);
+        let pkt = af_packet(&body);
+        assert_eq!(pkt.pcr(), 0x1_2345_6789 * 300 + 0x123);
+        assert_eq!(pkt.splice_countdown(), 5);
+        assert_eq!(pkt.transport_private_data(), &private);
+        assert_eq!(pkt.extension(), &extension);
+
+        // everything flagged
+        let mut body = vec![0x1F];
+        body.extend_from_slice(&pcr_bytes(1000, 1));
+        body.extend_from_slice(&pcr_bytes(2000, 2));
+        body.extend_from_slice(&[0xFE, 4]);
+        body.extend_from_slice(&private);
+        body.push(2);
+        body.extend_from_slice(&extension);
+        let pkt = af_packet(&body);
+        assert_eq!(pkt.pcr(), 1000 * 300 + 1);
+        assert_eq!(pkt.opcr(), 2000 * 300 + 2);
+        assert_eq!(pkt.splice_countdown(), 0xFE);
+        assert_eq!(pkt.transport_private_data(), &private);
+        assert_eq!(pkt.extension(), &extension);
+
+        // extension alone, no private data to skip
+        let pkt = af_packet(&[0x01, 2, 0x1F, 0xAB]);
+        assert_eq!(pkt.transport_private_data(), &[] as &[u8]);
+        assert_eq!(pkt.extension(), &extension);
+    }
+
+    #[test]
+    fn arrival_timestamp_jitter() {
+        let mut first = vec![0x10];
+        first.extend_from_slice(&pcr_bytes(90_000, 0));
+        let mut second = vec![0x10];
+        second.extend_from_slice(&pcr_bytes(90_900, 0));
+        let first = af_packet(&first);
+        let second = af_packet(&second);
+        // 10ms of PCR between them, arriving 10ms plus 270 ticks apart
+        let first_ats = 0x3FFF_FF00;
+        let second_ats = (first_ats + 270_270) & 0x3FFF_FFFF;
+        assert_eq!(arrival_delta(first_ats, second_ats), 270_270);
+        assert_eq!(
+            arrival_jitter((first_ats, first.pcr()), (second_ats, second.pcr())),
+            -270
+        );
+    }
+
+    #[test]
+    fn arrival_jitter_across_pcr_wrap() {
+        // 10ms of PCR that wraps part way, arriving 270 ticks early
+        let earlier = (1_000, PCR_WRAP - 100_000);
+        let later = (1_000 + 270_000 - 270, 170_000);
+        assert_eq!(arrival_jitter(earlier, later), 270);
+    }
+
+    #[test]
+    fn m2ts_reader_timestamps() {
+        let capture = sample_capture();
+        let first = Packet::new(capture[..188].try_into().unwrap());
+        let second = Packet::new(capture[188..376].try_into().unwrap());
+        let mut m2ts = first.to_m2ts(0x3FFF_FF00).to_vec();
+        m2ts.extend_from_slice(&second.to_m2ts(0x100));
+        let mut reader = PacketReader::new_m2ts(&m2ts[..]);
+        let (first_ats, pkt) = reader.next_timed_packet().unwrap().unwrap();
+        assert_eq!(pkt.data(), first.data());
+        let (second_ats, pkt) = reader.next_timed_packet().unwrap().unwrap();
+        assert_eq!(pkt.data(), second.data());
+        assert_eq!(arrival_delta(first_ats, second_ats), 0x200);
+        assert_eq!(reader.next_timed_packet().unwrap().is_none(), true);
+
+        let mut plain = PacketReader::new(&capture[..]);
+        let err = plain.next_timed_packet().err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn pmt_pid_validation() {
+        let duplicate = Packet::new(create_pmt_packet(0x1000, &[(256, 27), (256, 15)], 0));
+        let tables = duplicate.tables().unwrap();
+        let section = tables.section_data();
+        assert_eq!(
+            section.table_data().validate_pids(),
+            Err(PmtError::DuplicatePid(256))
+        );
+
+        let reserved = Packet::new(create_pmt_packet(0x1000, &[(256, 27), (0x1FFF, 15)], 0));
+        let tables = reserved.tables().unwrap();
+        let section = tables.section_data();
+        assert_eq!(
+            section.table_data().validate_pids(),
+            Err(PmtError::ReservedPid(0x1FFF))
+        );
+
+        let sample = Packet::new(hex_to_bin(SAMPLE_PMT_HEX));
+        let tables = sample.tables().unwrap();
+        let section = tables.section_data();
+        assert_eq!(section.table_data().validate_pids(), Ok(()));
+    }
+
+    #[test]
+    fn synth_packet_full() {
+        let header = HeaderFields {
+            tei: false,
+            pusi: true,
+            priority: false,
+            pid: 0x100,
+            tsc: 0,
+            afc: 3,
+            cc: 2,
+        };
+        let af = [0x40, 0xFF, 0xFF];
+        let payload = [0x5A; 180];
+        let pkt = Packet::new(Packet::create_packet_full(header, Some(&af), &payload).unwrap());
+        assert_eq!(pkt.pid(), 0x100);
+        assert_eq!(pkt.pusi(), true);
+        assert_eq!(pkt.afc(), 3);
+        assert_eq!(pkt.cc(), 2);
+        assert_eq!(pkt.aflen(), 3);
+        assert_eq!(pkt.is_random_access(), true);
+        assert_eq!(pkt.payload_data(), &payload[..]);
+
+        assert_eq!(
+            Packet::create_packet_full(header, Some(&af), &[0x5A; 181]),
+            Err(PacketError::TooLarge(185))
+        );
+        // a short payload with no adaptation field given still needs one for stuffing
+        let short = Packet::new(Packet::create_packet_full(header, None, &[0x5A; 10]).unwrap());
+        assert_eq!(short.aflen(), 173);
+        assert_eq!(short.payload_data(), &[0x5A; 10]);
+        let payload_only = HeaderFields { afc: 1, ..header };
+        assert_eq!(
+            Packet::create_packet_full(payload_only, None, &[0x5A; 10]),
+            Err(PacketError::AfcMismatch {
+                requested: 1,
+                required: 3
+            })
+        );
+    }
+
+    #[test]
+    fn resync_after_short_packet() {
+        let capture = sample_capture();
+        assert_eq!(resync(&capture), Some(0));
+
+        // lose the last 3 bytes of the first packet
+        let mut truncated = capture[..185].to_vec();
+        truncated.extend_from_slice(&capture[188..]);
+        truncated.extend_from_slice(&hex_to_bin(SAMPLE_PAYLOAD_HEX));
+        assert_eq!(resync(&truncated), Some(185));
+        let recovered = Packet::new(truncated[185..185 + 188].try_into().unwrap());
+        assert_eq!(recovered.pid(), 0x1000);
+
+        assert_eq!(resync(&[0u8; 1000]), None);
+        // a lone 0x47 with no room for the next packet's sync proves nothing
+        let mut lone = vec![0u8; 100];
+        lone.push(0x47);
+        lone.extend_from_slice(&[0u8; 50]);
+        assert_eq!(resync(&lone), None);
+    }
+
+    #[test]
+    fn packet_reader_reports_resync() {
+        let stream = create_two_program_stream();
+        // packet 2 comes up 3 bytes short
+        let mut input = stream[..2 * 188 + 100].to_vec();
+        input.extend_from_slice(&stream[2 * 188 + 103..]);
+        let mut reader = PacketReader::new(&input[..]);
+        let mut events = Vec::new();
+        while let Some(event) = reader.next_event().unwrap() {
+            events.push(event);
+        }
+        // the short packet swallows the start of packet 3, the rest of which is dropped
+        // one packet lost and a resync reported in its place
+        assert_eq!(events.len(), stream.len() / 188);
+        assert_eq!(
+            events[0],
+            ReadEvent::Packet(stream[..188].try_into().unwrap())
+        );
+        assert_eq!(events[3], ReadEvent::ReSync { dropped_bytes: 185 });
+        for (event, window) in events[4..].iter().zip(stream[4 * 188..].chunks_exact(188)) {
+            assert_eq!(*event, ReadEvent::Packet(window.try_into().unwrap()));
+        }
+        assert_eq!(reader.skipped(), 185);
+    }
+
+    #[test]
+    fn splice_timeline() {
+        let mut body = vec![0x10];
+        body.extend_from_slice(&pcr_bytes(90_000, 17));
+        let pcr_pkt = af_packet(&body);
+        let input_pcr = pcr_pkt.pcr();
+        let delta = 27_000_000 * 5 + 123;
+        let splicer = TimelineSplicer::new(delta, true);
+        let rebased = Packet::new(splicer.rebase(pcr_pkt));
+        assert_eq!(rebased.pcr(), input_pcr + delta);
+
+        // PTS and DTS move by the same amount in 90kHz ticks
+        let pes: &[u8] = &[
+            0x00, 0x00, 0x01, 0xE0, 0x00, 0x00, 0x80, 0xC0, 0x0A, 0x31, 0x00, 0x09, 0x12, 0xF9,
+            0x11, 0x00, 0x07, 0xD8, 0x61,
+        ];
+        let header = HeaderFields {
+            tei: false,
+            pusi: true,
+            priority: false,
+            pid: 0x100,
+            tsc: 0,
+            afc: 3,
+            cc: 0,
+        };
+        let pes_pkt = Packet::new(Packet::create_packet_full(header, None, pes).unwrap());
+        let (pts, dts) = (pes.pts().unwrap(), pes.dts().unwrap());
+        let rebased = Packet::new(splicer.rebase(pes_pkt));
+        let rebased_pes = rebased.payload_data();
+        assert_eq!(rebased_pes.pts(), Some(pts + delta / 300));
+        assert_eq!(rebased_pes.dts(), Some(dts + delta / 300));
+        assert_eq!(&rebased_pes[0..9], &pes[0..9]);
+    }
+
+    #[test]
+    fn sample_pid_set() {
+        let pids: Vec<u16> = pid_set(&sample_capture()).into_iter().collect();
+        assert_eq!(pids, vec![0, 0x100, 0x1000]);
+    }
+
+    #[test]
+    fn eit_kinds() {
+        let body = [0x00, 0x01, 0x00, 0x02, 0x00, 0x4E];
+        let present_following = long_section(0x4E, 0x10, 0, 0, &body);
+        let section = present_following.as_slice();
+        assert_eq!(section.kind(), EitKind::PresentFollowing);
+        assert_eq!(section.service_id(), 0x10);
+        assert_eq!(section.transport_stream_id(), 1);
+        assert_eq!(section.original_network_id(), 2);
+        let schedule = long_section(0x50, 0x10, 0, 0, &body);
+        assert_eq!(schedule.as_slice().kind(), EitKind::Schedule);
+        let other_schedule = long_section(0x6F, 0x10, 0, 0, &body);
+        assert_eq!(other_schedule.as_slice().kind(), EitKind::OtherSchedule);
+        let other_pf = long_section(0x4F, 0x10, 0, 0, &body);
+        assert_eq!(other_pf.as_slice().kind(), EitKind::OtherPresentFollowing);
+    }
+
+    #[test]
+    fn reassemble_sections_into_scratch() {
+        // a 303 byte section split across two packets
+        let body: Vec<u8> = (0..291).map(|b| b as u8).collect();
+        let section = long_section(0x02, 1, 0, 0, &body);
+        let mut packets = Vec::new();
+        for n in 0..100 {
+            let mut first = Packet::create_packet(false, true, false, 0x1000, 0, 1, (2 * n) as u8);
+            first[4] = 0;
+            first[5..].copy_from_slice(&section[..183]);
+            let mut second =
+                Packet::create_packet(false, false, false, 0x1000, 0, 1, (2 * n + 1) as u8);
+            second[4..4 + section.len() - 183].copy_from_slice(&section[183..]);
+            packets.push(Packet::new(first));
+            packets.push(Packet::new(second));
+        }
+
+        let mut assembler = SectionAssembler::new();
+        let mut scratch = [0u8; 1024];
+        let mut count = 0;
+        let mut matched = 0;
+        let before = allocation_count();
+        for pkt in packets.iter() {
+            assembler
+                .push_into(pkt, &mut scratch, |assembled| {
+                    count += 1;
+                    if assembled == section.as_slice() && calc_crc32(assembled) == assembled.crc32()
+                    {
+                        matched += 1;
+                    }
+                })
+                .unwrap();
+        }
+        assert_eq!(allocation_count(), before);
+        assert_eq!(count, 100);
+        assert_eq!(matched, 100);
+
+        // two sections back to back in one packet
+        let pat = pat_section(0, 0, &[(1, 0x100)]);
+        let mut both = Packet::create_packet(false, true, false, 0, 0, 1, 0);
+        both[4] = 0;
+        both[5..5 + pat.len()].copy_from_slice(&pat);
+        both[5 + pat.len()..5 + 2 * pat.len()].copy_from_slice(&pat);
+        let sections = SectionAssembler::new().push(&Packet::new(both), &mut scratch);
+        assert_eq!(sections, vec![pat.clone(), pat]);
+    }
+
+    #[test]
+    fn payload_data_skips_af_length_byte() {
+        // adaptation_field_length 7 with a PCR, the payload opening on a PES start code
+        let mut af_body = vec![0x10];
+        af_body.extend_from_slice(&pcr_bytes(1, 0));
+        let mut data = *af_packet(&af_body).data();
+        data[12..15].copy_from_slice(&[0x00, 0x00, 0x01]);
+        let pkt = Packet::new(data);
+        assert_eq!(pkt.aflen(), 7);
+        // 4 header bytes, the length byte then the 7 byte field. Before this was fixed the
+        // payload started at 4 + aflen and so opened on the last byte of the field
+        assert_eq!(pkt.payload_data().len(), 188 - 12);
+        assert_eq!(&pkt.payload_data()[..3], &[0x00, 0x00, 0x01]);
+        assert_eq!(pkt.as_packet_ref().payload_data(), pkt.payload_data());
+
+        // an adaptation_field_length running past the packet leaves no payload
+        data[4] = 190;
+        assert_eq!(Packet::new(data).payload_data(), &[] as &[u8]);
+    }
+
+    #[test]
+    fn tables_after_adaptation_field() {
+        let pat_pkt = Packet::new(create_pat_packet(&[0x1000], 0));
+        let pat_tables = pat_pkt.tables().unwrap();
+        let section = pat_tables.section_data();
+
+        // the same PAT behind a stuffing-only adaptation field, pointer field then section
+        let af = stuffing_only_af(10);
+        let mut data = Packet::create_packet(false, true, false, 0, 0, 3, 0);
+        data[4..4 + af.len()].copy_from_slice(&af);
+        let offset = 4 + af.len();
+        data[offset] = 0;
+        data[offset + 1..offset + 1 + section.len()].copy_from_slice(section);
+        data[offset + 1 + section.len()..].fill(0xFF);
+
+        let pkt = Packet::new(data);
+        assert_eq!(pkt.payload_data().len(), 188 - offset);
+        assert_eq!(pkt.payload_data()[0], 0);
+        let tables = pkt.tables().unwrap();
+        assert_eq!(tables.section_data(), section);
+        let pkt_ref = pkt.as_packet_ref();
+        let ref_tables = pkt_ref.tables().unwrap();
+        assert_eq!(ref_tables.section_data(), section);
+    }
+
+    #[test]
+    fn pmt_equal_apart_from_version() {
+        let original = create_pmt_packet(0x1000, &[(256, 27), (257, 15)], 0);
+        let original_pkt = Packet::new(original);
+        let tables = original_pkt.tables().unwrap();
+        let section = tables.section_data();
+
+        let mut bumped = section.to_vec();
+        bumped[5] = 0xC0 | (3 << 1) | 1;
+        let crc_idx = bumped.len() - 4;
+        let crc = calc_crc32(&bumped);
+        BigEndian::write_u32(&mut bumped[crc_idx..], crc);
+        assert_eq!(bumped.as_slice().version(), 3);
+        assert_eq!(pmt_content_eq(section, &bumped), true);
+
+        let changed_pkt = Packet::new(create_pmt_packet(0x1000, &[(256, 27), (258, 15)], 0));
+        let changed_tables = changed_pkt.tables().unwrap();
+        assert_eq!(
+            pmt_content_eq(section, changed_tables.section_data()),
+            false
+        );
+    }
+
+    #[test]
+    fn pmt_content_eq_malformed() {
+        let pkt = Packet::new(create_pmt_packet(0x1000, &[(256, 27)], 0));
+        let tables = pkt.tables().unwrap();
+        let section = tables.section_data();
+
+        // program_info_length claims far more than the section holds
+        let mut malformed = section.to_vec();
+        malformed[10] = 0xF3;
+        malformed[11] = 0xFF;
+        assert_eq!(pmt_content_eq(&malformed, &malformed), false);
+        assert_eq!(pmt_content_eq(section, &malformed), false);
+        assert_eq!(pmt_content_eq(&malformed, section), false);
+    }
+
+    #[test]
+    fn m2ts_packet_round_trip() {
+        let capture = sample_capture();
+        let pkt = Packet::new(capture[..188].try_into().unwrap());
+        let m2ts = pkt.to_m2ts(0x1234_5678);
+        assert_eq!(&m2ts[..4], &[0x12, 0x34, 0x56, 0x78]);
+        assert_eq!(&m2ts[4..], &capture[..188]);
+        let (timestamp, back) = Packet::from_m2ts(&m2ts);
+        assert_eq!(timestamp, 0x1234_5678);
+        assert_eq!(back.data(), pkt.data());
+        assert_eq!(back.pid(), 0);
+
+        // copy_permission_indicator set on the way in is dropped from the timestamp
+        let mut protected = m2ts;
+        protected[0] |= 0xC0;
+        assert_eq!(Packet::from_m2ts(&protected).0, 0x1234_5678);
+        // and a timestamp too wide for 30 bits never reaches it
+        assert_eq!(pkt.to_m2ts(0xFFFF_FFFF)[0], 0x3F);
+    }
+
+    #[test]
+    fn m2ts_write_round_trip() {
+        let capture = sample_capture();
+        let mut writer = TsWriter::new_m2ts(Vec::new(), 1_504_000);
+        for window in capture.chunks_exact(188) {
+            writer.write_packet(window.try_into().unwrap()).unwrap();
+        }
+        let m2ts = writer.into_inner();
+        assert_eq!(m2ts.len(), 3 * 192);
+
+        let mut reader = PacketReader::new_m2ts(&m2ts[..]);
+        let mut last_timestamp = None;
+        for original in capture.chunks_exact(188) {
+            let (timestamp, pkt) = reader.next_timed_packet().unwrap().unwrap();
+            if let Some(last) = last_timestamp {
+                // 1ms per packet at this bitrate
+                assert_eq!(timestamp > last, true);
+                assert_eq!(arrival_delta(last, timestamp), 27_000);
+            }
+            last_timestamp = Some(timestamp);
+            assert_eq!(&pkt.data()[..], original);
+        }
+        assert_eq!(reader.next_timed_packet().unwrap().is_none(), true);
+        assert_eq!(reader.skipped(), 0);
+    }
+
+    #[test]
+    fn validate_muxed_stream() {
+        let mut stream = Vec::new();
+        stream.extend_from_slice(&create_pat_packet(&[0x1000], 0));
+        stream.extend_from_slice(&create_pmt_packet(
+            0x1000,
+            &[(0x100, 0x1B), (0x101, 0x0F)],
+            0,
+        ));
+        for cc in 0..20u8 {
+            for pid in [0x100, 0x101].iter() {
+                let pkt = Packet::create_packet(false, cc == 0, false, *pid, 0, 1, cc & 0xF);
+                stream.extend_from_slice(&pkt);
+            }
+        }
+        let report = validate_stream(&stream);
+        assert_eq!(report.packets, 42);
+        assert_eq!(report.is_clean(), true);
+
+        // last packet on PID 0x101 skips a cc
+        let last_cc = stream.len() - 188 + 3;
+        stream[last_cc] = (stream[last_cc] & 0xF0) | ((stream[last_cc] + 1) & 0xF);
+        let report = validate_stream(&stream);
+        assert_eq!(report.cc_errors, 1);
+        assert_eq!(report.crc_errors, 0);
+        assert_eq!(report.sync_errors, 0);
+    }
+
+    #[test]
+    fn validate_interleaved_sections() {
+        let report = validate_stream(&interleaved_pmt_capture());
+        assert_eq!(report.packets, 4);
+        assert_eq!(report.crc_errors, 0);
+        assert_eq!(report.is_clean(), true);
+    }
+
+    #[test]
+    fn rewrite_info_lengths() {
+        let pmt = Packet::new(create_pmt_packet(0x1000, &[(0x100, 0x1B)], 0));
+        let tables = pmt.tables().unwrap();
+        let mut section = tables[..tables.section_length() as usize + 3].to_vec();
+        set_program_info_len(&mut section, 12);
+        assert_eq!(section[10], 0xF0);
+        assert_eq!((&section[..]).table_data().program_info_len(), 12);
+
+        set_program_info_len(&mut section, 0x3FF);
+        assert_eq!((&section[..]).table_data().program_info_len(), 0x3FF);
+
+        let mut es = [0x1B, 0xE1, 0x00, 0xF0, 0x00];
+        set_es_info_len(&mut es, 6);
+        assert_eq!(&es[3..], &[0xF0, 6]);
+        assert_eq!((&es[..]).es_info_len(), 6);
+    }
+
+    #[test]
+    fn private_section_data() {
+        let long = long_section(0xC0, 7, 0, 0, b"custom payload");
+        let section = PrivateSection::new(&long).unwrap();
+        assert_eq!(section.table_id(), 0xC0);
+        assert_eq!(section.has_syntax_section(), true);
+        assert_eq!(section.section_length() as usize, long.len() - 3);
+        assert_eq!(section.private_data(), b"custom payload");
+
+        // short form with the private indicator set and trailing stuffing
+        let mut short = vec![0x40, 0x70, 5, 1, 2, 3, 4, 5];
+        short.extend_from_slice(&[0xFF; 4]);
+        let section = PrivateSection::new(&short).unwrap();
+        assert_eq!(section.has_syntax_section(), false);
+        assert_eq!(section.private_indicator(), true);
+        assert_eq!(section.private_data(), &[1, 2, 3, 4, 5]);
+
+        assert_eq!(PrivateSection::new(&short[..6]), None);
+        assert_eq!(
+            PrivateSection::new(&pat_section(0, 0, &[(1, 0x1000)])),
+            None
+        );
+    }
+
+    #[test]
+    fn interpolate_pts() {
+        let mut interpolator = PtsInterpolator::new(25, 1);
+        assert_eq!(interpolator.next(Some(90_000)), 90_000);
+        assert_eq!(interpolator.next(None), 93_600);
+        assert_eq!(interpolator.next(None), 97_200);
+        assert_eq!(interpolator.next(None), 100_800);
+        // a real PTS always wins over the interpolated one
+        assert_eq!(interpolator.next(Some(200_000)), 200_000);
+        assert_eq!(interpolator.next(None), 203_600);
+
+        let mut ntsc = PtsInterpolator::new(30000, 1001);
+        ntsc.next(Some(0));
+        for _ in 0..999 {
+            ntsc.next(None);
+        }
+        assert_eq!(ntsc.next(None), 1000 * 3003);
+    }
+
+    #[test]
+    fn ring_demux_odd_chunks() {
+        let mut stream = Vec::new();
+        for n in 0..40u16 {
+            stream.extend_from_slice(&Packet::create_packet(
+                false,
+                false,
+                false,
+                0x100 + n,
+                0,
+                1,
+                (n & 0xF) as u8,
+            ));
+        }
+        let mut demux = RingDemux::new(4);
+        let mut next_pid = 0x100;
+        let mut pos = 0;
+        let before = allocation_count();
+        for size in [1, 7, 100, 250, 188, 187, 189, 500].iter().cycle() {
+            if pos >= stream.len() {
+                break;
+            }
+            let end = (pos + size).min(stream.len());
+            demux.push(&stream[pos..end]);
+            pos = end;
+            for pkt in demux.drain() {
+                assert_eq!(pkt.pid(), next_pid);
+                next_pid += 1;
+            }
+        }
+        assert_eq!(allocation_count(), before);
+        assert_eq!(next_pid, 0x100 + 40);
+        assert_eq!(demux.dropped(), 0);
+    }
+
+    #[test]
+    fn ring_demux_overflow_burst() {
+        // payloads full of 0x47 so anything off a packet boundary would look like a sync byte
+        let mut stream = Vec::new();
+        for n in 0..10u16 {
+            let data = Packet::create_packet_with_payload(
+                false,
+                false,
+                false,
+                0x100 + n,
+                0,
+                1,
+                0,
+                &[0x47; 184],
+            );
+            stream.extend_from_slice(&data.unwrap());
+        }
+        let pids = |demux: &mut RingDemux| demux.drain().map(|pkt| pkt.pid()).collect::<Vec<u16>>();
+
+        // more than the ring holds in one go keeps the newest whole packets
+        let mut demux = RingDemux::new(4);
+        demux.push(&stream);
+        assert_eq!(pids(&mut demux), vec![0x106, 0x107, 0x108, 0x109]);
+        assert_eq!(demux.dropped(), 6 * 188);
+
+        // overflowing by half a packet drops the whole oldest packet
+        let mut demux = RingDemux::new(4);
+        demux.push(&stream[..658]);
+        demux.push(&stream[658..846]);
+        assert_eq!(pids(&mut demux), vec![0x101, 0x102, 0x103]);
+        demux.push(&stream[846..940]);
+        assert_eq!(pids(&mut demux), vec![0x104]);
+        assert_eq!(demux.dropped(), 188);
+
+        // a stray 0x47 in the junk isn't taken as sync without another a packet on
+        let mut input = vec![0x00, 0x47];
+        input.extend_from_slice(&[0xAA; 10]);
+        for pid in [0x200, 0x201].iter() {
+            input.extend_from_slice(&Packet::create_packet(false, false, false, *pid, 0, 1, 0));
+        }
+        let mut demux = RingDemux::new(8);
+        demux.push(&input);
+        assert_eq!(pids(&mut demux), vec![0x200, 0x201]);
+        assert_eq!(demux.dropped(), 12);
+    }
+
+    #[test]
+    fn pcr_discontinuity_resets_estimators() {
+        // PCR every 10th packet at 15.04Mbps, so 27000 ticks per 10 packets
+        let mut packets = Vec::new();
+        let mut pcr_base = 0;
+        for n in 0..60u64 {
+            if n % 10 == 0 {
+                let mut af = vec![0x10];
+                if n == 30 {
+                    // new timebase way off from the old one
+                    pcr_base = 50_000_000;
+                    af[0] |= 0x80;
+                }
+                af.extend_from_slice(&pcr_bytes((pcr_base + n * 2_700) / 300, 0));
+                packets.push(af_packet(&af));
+            } else {
+                packets.push(Packet::new(Packet::create_packet(
+                    false, false, false, 256, 0, 1, 0,
+                )));
+            }
+        }
+
+        let mut estimator = BitrateEstimator::new(256);
+        let mut analyzer = PcrAnalyzer::new(256);
+        for (n, pkt) in packets.iter().enumerate() {
+            estimator.push(pkt);
+            analyzer.push(pkt);
+            if n == 30 {
+                assert_eq!(estimator.bitrate(), None);
+                assert_eq!(analyzer.last_jitter(), None);
+            }
+        }
+        assert_eq!(estimator.bitrate(), Some(15_040_000));
+        assert_eq!(analyzer.last_jitter(), Some(0));
+        assert_eq!(analyzer.max_jitter(), 0);
+        assert_eq!(analyzer.resets(), 1);
+    }
+
+    #[test]
+    fn bitrate_across_pcr_wrap() {
+        // a PCR an hour apart every 10000 packets, running well past the 26.5h PCR wrap
+        let filler = Packet::new(Packet::create_packet(false, false, false, 256, 0, 1, 0));
+        let mut estimator = BitrateEstimator::new(256);
+        for hour in 0..30u64 {
+            let base = (hour * 90_000 * 3600) % (1 << 33);
+            let mut af = vec![0x10];
+            af.extend_from_slice(&pcr_bytes(base, 0));
+            estimator.push(&af_packet(&af));
+            for _ in 0..9_999 {
+                estimator.push(&filler);
+            }
+        }
+        assert_eq!(estimator.bitrate(), Some(10_000 * 188 * 8 / 3600));
+    }
+
+    #[test]
+    fn sdt_and_bat_on_one_pid() {
+        // original_network_id 0x2000 then one service with a 3 byte descriptor
+        let sdt_body = [
+            0x20, 0x00, 0xFF, 0x00, 0x0A, 0xFD, 0x90, 0x03, 0x48, 0x01, 0x01,
+        ];
+        // bouquet descriptors, then one transport stream with no descriptors
+        let bat_body = [
+            0xF0, 0x02, 0x47, 0x00, 0xF0, 0x06, 0x00, 0x05, 0x20, 0x00, 0xF0, 0x00,
+        ];
+        let sdt = long_section(0x42, 0x0005, 0, 0, &sdt_body);
+        let bat = long_section(0x4A, 0x1234, 0, 0, &bat_body);
+
+        let mut collector = SdtBatCollector::new();
+        let mut tables = collector.push(&Packet::new(psi_packet(SDT_BAT_PID, &sdt, 0)));
+        tables.extend(collector.push(&Packet::new(psi_packet(SDT_BAT_PID, &bat, 1))));
+        // ignored on any other PID
+        assert_eq!(
+            collector
+                .push(&Packet::new(psi_packet(0x12, &sdt, 0)))
+                .len(),
+            0
+        );
+        assert_eq!(tables.len(), 2);
+
+        match &tables[0] {
+            SdtBatTable::Sdt(sdt) => {
+                assert_eq!(sdt.actual, true);
+                assert_eq!(sdt.transport_stream_id, 5);
+                assert_eq!(sdt.original_network_id, 0x2000);
+                assert_eq!(sdt.services.len(), 1);
+                assert_eq!(sdt.services[0].service_id, 0x000A);
+                assert_eq!(sdt.services[0].eit_schedule, false);
+                assert_eq!(sdt.services[0].eit_present_following, true);
+                assert_eq!(sdt.services[0].running_status, 4);
+                assert_eq!(sdt.services[0].descriptors, vec![0x48, 0x01, 0x01]);
+            }
+            other => panic!("expected an SDT, got {:?}", other),
+        }
+        match &tables[1] {
+            SdtBatTable::Bat(bat) => {
+                assert_eq!(bat.bouquet_id, 0x1234);
+                assert_eq!(bat.descriptors, vec![0x47, 0x00]);
+                assert_eq!(bat.transports.len(), 1);
+                assert_eq!(bat.transports[0].transport_stream_id, 5);
+                assert_eq!(bat.transports[0].original_network_id, 0x2000);
+            }
+            other => panic!("expected a BAT, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn oversized_section_resets() {
+        let mut scratch = vec![0u8; 8192];
+        // section_length of 0xFFF claims 4098 bytes
+        let bogus = [0x80, 0xBF, 0xFF, 0x00, 0x01, 0xC1, 0x00, 0x00];
+        let mut assembler = SectionAssembler::new();
+        let result = assembler.push_into(
+            &Packet::new(psi_packet(0x1000, &bogus, 0)),
+            &mut scratch,
+            |_| panic!("nothing should be assembled"),
+        );
+        assert_eq!(
+            result,
+            Err(SectionError::SectionTooLarge {
+                size: 4098,
+                max: 4096
+            })
+        );
+        // the continuation of the abandoned section is ignored
+        let follow_on = Packet::create_packet(false, false, false, 0x1000, 0, 1, 1);
+        assert_eq!(
+            assembler.push(&Packet::new(follow_on), &mut scratch).len(),
+            0
+        );
+
+        // and the next good section comes through
+        let section = pat_section(0, 0, &[(1, 0x1000)]);
+        let good = Packet::new(psi_packet(0x1000, &section, 2));
+        assert_eq!(assembler.push(&good, &mut scratch), vec![section.clone()]);
+
+        let mut strict = SectionAssembler::with_max_section_size(12);
+        let result = strict.push_into(&good, &mut scratch, |_| {});
+        assert_eq!(
+            result,
+            Err(SectionError::SectionTooLarge { size: 16, max: 12 })
+        );
+    }
+
+    #[test]
+    fn pes_data_alignment() {
+        // data_alignment_indicator set, no PTS/DTS
+        let video = [0, 0, 1, 0xE0, 0, 0, 0x84, 0, 0, 0, 0, 1, 0x09, 0xF0];
+        assert_eq!((&video[..]).data_alignment_indicator(), true);
+        assert_eq!(check_alignment(&video, StreamType::H264), Ok(()));
+
+        let mut misaligned = video;
+        misaligned[11] = 0x02;
+        assert_eq!(
+            check_alignment(&misaligned, StreamType::H264),
+            Err(PesError::MisalignedPes { stream_id: 0xE0 })
+        );
+        // without the indicator nothing is claimed
+        misaligned[6] = 0x80;
+        assert_eq!(check_alignment(&misaligned, StreamType::H264), Ok(()));
+
+        let audio = [0, 0, 1, 0xC0, 0, 0, 0x84, 0, 0, 0xFF, 0xF1, 0x50];
+        assert_eq!(check_alignment(&audio, StreamType::AdtsAac), Ok(()));
+        assert_eq!(
+            check_alignment(&audio[..10], StreamType::AdtsAac),
+            Err(PesError::MisalignedPes { stream_id: 0xC0 })
+        );
+    }
+
+    #[test]
+    fn eit_round_trip() {
+        let event = EventSpec {
+            event_id: 0x0101,
+            // 2026-10-14 20:30:00 for an hour and a half
+            start_time: [0xEF, 0x8F, 0x20, 0x30, 0x00],
+            duration: [0x01, 0x30, 0x00],
+            running_status: 4,
+            language: *b"eng",
+            name: b"Evening News".to_vec(),
+            text: vec![b'x'; 200],
+        };
+        let packets = create_eit_packet(0x0A, 5, 0x2000, std::slice::from_ref(&event), 3).unwrap();
+        // the long text pushes the section into a second packet
+        assert_eq!(packets.len(), 2);
+        assert_eq!(Packet::new(packets[1]).cc(), 4);
+
+        let mut assembler = SectionAssembler::new();
+        let mut scratch = vec![0u8; 1024];
+        let mut sections = Vec::new();
+        for pkt in packets.iter() {
+            let pkt = Packet::new(*pkt);
+            assert_eq!(pkt.pid(), EIT_PID);
+            sections.extend(assembler.push(&pkt, &mut scratch));
+        }
+        assert_eq!(sections.len(), 1);
+        let section = sections[0].as_slice();
+        assert_eq!(calc_crc32(section), section.crc32());
+        assert_eq!(section.kind(), EitKind::PresentFollowing);
+        assert_eq!(section.service_id(), 0x0A);
+        assert_eq!(section.transport_stream_id(), 5);
+        assert_eq!(section.original_network_id(), 0x2000);
+        assert_eq!(parse_eit_events(section), vec![event]);
+    }
+
+    #[test]
+    fn eit_refuses_oversized_events() {
+        let mut event = EventSpec {
+            event_id: 7,
+            start_time: [0xEF, 0x8F, 0x20, 0x30, 0x00],
+            duration: [0x01, 0x30, 0x00],
+            running_status: 4,
+            language: *b"eng",
+            name: Vec::new(),
+            text: vec![b'x'; 250],
+        };
+        // 3 language bytes, two length bytes and the text only just fit
+        assert_eq!(
+            create_eit_packet(0x0A, 5, 0x2000, std::slice::from_ref(&event), 0).is_ok(),
+            true
+        );
+        event.text.push(b'x');
+        assert_eq!(
+            create_eit_packet(0x0A, 5, 0x2000, std::slice::from_ref(&event), 0),
+            Err(EitError::DescriptorTooLong {
+                event_id: 7,
+                len: 256
+            })
+        );
+
+        // 20 events of 269 bytes each run past the section limit
+        event.text.pop();
+        let events = vec![event; 20];
+        assert_eq!(
+            create_eit_packet(0x0A, 5, 0x2000, &events, 0),
+            Err(EitError::SectionTooLarge {
+                size: 14 + 20 * 269 + 4,
+                max: 4096
+            })
+        );
+    }
+
+    #[test]
+    fn pes_timestamp_order() {
+        fn pes_with(pts: u64, dts: u64) -> Vec<u8> {
+            let mut pes = vec![0, 0, 1, 0xE0, 0, 0, 0x80, 0xC0, 10];
+            pes.extend_from_slice(&[0x31, 0, 0, 0, 0, 0x11, 0, 0, 0, 0]);
+            write_timestamp(&mut pes[9..14], pts);
+            write_timestamp(&mut pes[14..19], dts);
+            return pes;
+        }
+        let mut checker = TimestampOrderChecker::new();
+        assert_eq!(checker.check(&pes_with(10_800, 3_600)), vec![]);
+        assert_eq!(checker.check(&pes_with(7_200, 7_200)), vec![]);
+        assert_eq!(
+            checker.check(&pes_with(18_000, 5_400)),
+            vec![TimestampViolation::DtsRegression {
+                previous: 7_200,
+                dts: 5_400
+            }]
+        );
+        assert_eq!(
+            checker.check(&pes_with(9_000, 10_800)),
+            vec![TimestampViolation::PtsBeforeDts {
+                pts: 9_000,
+                dts: 10_800
+            }]
+        );
+        // counting on past the 33 bit wrap is fine
+        let mut checker = TimestampOrderChecker::new();
+        checker.check(&pes_with(PTS_WRAP - 1_800, PTS_WRAP - 3_600));
+        assert_eq!(checker.check(&pes_with(1_800, 0)), vec![]);
+    }
+
+    #[test]
+    fn section_fragment_is_incomplete() {
+        let pmt = Packet::new(create_pmt_packet(0x1000, &[(0x100, 0x1B)], 0));
+        let tables = pmt.tables().unwrap();
+        assert_eq!(tables.is_complete(tables.section_length()), true);
+
+        // claims 400 bytes, more than one packet can hold
+        let mut long = *pmt.data();
+        let offset = 188 - tables.len();
+        long[offset + 1] = 0xB1;
+        long[offset + 2] = 0x90;
+        let long = Packet::new(long);
+        let tables = long.tables().unwrap();
+        assert_eq!(tables.section_length(), 400);
+        assert_eq!(tables.is_complete(tables.section_length()), false);
+    }
+
+    #[test]
+    fn pmt_streams_with_descriptors() {
+        let body = [
+            0xE1, 0x00, 0xF0, 0x00, // PCR PID 0x100, no program descriptors
+            0x1B, 0xE1, 0x00, 0xF0, 0x00, // H.264 video
+            0x0F, 0xE1, 0x01, 0xF0, 0x06, 0x0A, 0x04, b'e', b'n', b'g', 0x00, // AAC audio
+        ];
+        let section = long_section(0x02, 1, 0, 0, &body);
+        let section = section.as_slice();
+        let pmt = section.table_data();
+        let streams: Vec<_> = pmt.streams_with_descriptors().collect();
+        assert_eq!(streams.len(), 2);
+        assert_eq!(streams[0], (StreamType::H264, 0x100, vec![]));
+        assert_eq!(
+            streams[1],
+            (
+                StreamType::AdtsAac,
+                0x101,
+                vec![Descriptor {
+                    tag: 0x0A,
+                    data: b"eng\x00"
+                }]
+            )
+        );
+    }
+
+    #[test]
+    fn pcr_diff_wraps() {
+        assert_eq!(pcr_diff(27_000, 54_000), 27_000);
+        assert_eq!(pcr_diff(54_000, 27_000), -27_000);
+        assert_eq!(pcr_diff(PCR_WRAP - 1_000, 500), 1_500);
+        assert_eq!(pcr_diff(500, PCR_WRAP - 1_000), -1_500);
+    }
+
+    #[test]
+    fn pts_pcr_conversion() {
+        // one second in on both clocks
+        assert_eq!(pts_from_pcr(27_000_000), 90_000);
+        assert_eq!(pcr_from_pts(90_000), 27_000_000);
+        // the extension is dropped on the way down
+        assert_eq!(pts_from_pcr(27_000_299), 90_000);
+        assert_eq!(pts_from_pcr(PCR_WRAP - 1), PTS_WRAP - 1);
+        assert_eq!(pcr_from_pts(PTS_WRAP - 1), PCR_WRAP - 300);
+        // video PTS leading the PCR by 100ms
+        assert_eq!(pcr_diff(27_000_000, pcr_from_pts(99_000)), 2_700_000);
+    }
+
+    #[test]
+    fn pid_checked_needs_sync() {
+        let good = Packet::create_packet(false, false, false, 0x1ABC, 0, 1, 0);
+        assert_eq!(Packet::new(good).pid_checked(), Some(0x1ABC));
+        let mut misaligned = good;
+        misaligned[0] = 0x00;
+        assert_eq!(Packet::new(misaligned).pid_checked(), None);
+    }
+
+    #[test]
+    fn pcr_on_video_pid() {
+        let language = [0x0A, 0x04, b'e', b'n', b'g', 0x00];
+        let streams: [(u16, u8, &[u8]); 2] = [(0x100, 0x1B, &[]), (0x101, 0x0F, &language)];
+        let packets = create_pmt_packet_with_descriptors(0x1000, 1, 0x100, &[], &streams, 0);
+        assert_eq!(packets.len(), 1);
+        let pmt = Packet::new(packets[0]);
+        let tables = pmt.tables().unwrap();
+        let section = tables.section_data();
+        assert_eq!(section.is_complete(section.section_length()), true);
+        assert_eq!(calc_crc32(section), section.crc32());
+        let table = section.table_data();
+        assert_eq!(table.valid_pmt(), true);
+        assert_eq!(table.pcr_pid(), 0x100);
+        assert_eq!(table.validate_pids(), Ok(()));
+        let es_pids: Vec<u16> = table
+            .streams_with_descriptors()
+            .map(|(_, pid, _)| pid)
+            .collect();
+        assert_eq!(es_pids, vec![0x100, 0x101]);
+
+        let extractor = ProgramExtractor::from_pmt(0x1000, table);
+        assert_eq!(extractor.pids(), vec![0, 0x100, 0x101, 0x1000]);
+        let mut mux = Vec::new();
+        mux.extend_from_slice(&create_pat_packet(&[0x1000], 0));
+        mux.extend_from_slice(&packets[0]);
+        for pid in [0x100, 0x101, 0x200].iter() {
+            mux.extend_from_slice(&Packet::create_packet(false, false, false, *pid, 0, 1, 0));
+        }
+        let extracted = extractor.extract(&mux);
+        assert_eq!(extracted.len(), 4 * 188);
+        assert_eq!(pid_set(&extracted).len(), 4);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn async_reader_sample() {
+        use crate::async_reader::AsyncTsReader;
+
+        // a few junk bytes up front to resync past
+        let mut input = vec![0x00, 0x47, 0x12];
+        input.extend_from_slice(&sample_capture());
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let pids = runtime.block_on(async {
+            let cursor = std::io::Cursor::new(input);
+            let mut reader = AsyncTsReader::new(tokio::io::BufReader::new(cursor));
+            let mut pids = Vec::new();
+            while let Some(pkt) = reader.next_packet().await.unwrap() {
+                pids.push(pkt.pid());
+            }
+            return pids;
+        });
+        assert_eq!(pids, vec![0, 0x1000, 0x100]);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn async_reader_truncated() {
+        use crate::async_reader::AsyncTsReader;
+
+        let capture = sample_capture();
+        let mut input = capture[..188].to_vec();
+        input.extend_from_slice(&capture[188..288]);
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let mut reader = AsyncTsReader::new(std::io::Cursor::new(input));
+            assert_eq!(reader.next_packet().await.unwrap().unwrap().pid(), 0);
+            assert_eq!(reader.trailing_bytes(), 0);
+            assert_eq!(reader.next_packet().await.unwrap().is_none(), true);
+            assert_eq!(reader.trailing_bytes(), 100);
+
+            let mut clean = AsyncTsReader::new(std::io::Cursor::new(capture));
+            while clean.next_packet().await.unwrap().is_some() {}
+            assert_eq!(clean.trailing_bytes(), 0);
+        });
+    }
+
+    #[test]
+    fn packet_reader_resync() {
+        let capture = sample_capture();
+        let mut reader = PacketReader::new(std::io::Cursor::new(vec![0x00; 10]));
+        assert_eq!(reader.next_packet().unwrap(), None);
+        assert_eq!(reader.skipped(), 10);
+
+        let mut input = vec![0x00, 0x00];
+        input.extend_from_slice(&capture);
+        let mut reader = PacketReader::new(std::io::Cursor::new(input));
+        let mut pids = Vec::new();
+        while let Some(data) = reader.next_packet().unwrap() {
+            pids.push(Packet::new(data).pid());
+        }
+        assert_eq!(pids, vec![0, 0x1000, 0x100]);
+        assert_eq!(reader.skipped(), 2);
+    }
+
+    #[test]
+    fn packet_reader_truncated() {
+        let capture = sample_capture();
+        let mut reader = PacketReader::new(&capture[..288]);
+        assert_eq!(reader.next_packet().unwrap().unwrap()[..], capture[..188]);
+        assert_eq!(reader.trailing_bytes(), 0);
+        assert_eq!(reader.next_packet().unwrap(), None);
+        assert_eq!(reader.trailing_bytes(), 100);
+        assert_eq!(reader.skipped(), 0);
+
+        let mut clean = PacketReader::new(&capture[..]);
+        while clean.next_packet().unwrap().is_some() {}
+        assert_eq!(clean.trailing_bytes(), 0);
+    }
+
+    #[test]
+    fn packet_size_detection() {
+        let stream = create_two_program_stream();
+        assert_eq!(detect_packet_size(&stream), Some(188));
+
+        let mut writer = TsWriter::new_m2ts(Vec::new(), 1_504_000);
+        let mut dvb = vec![0x00, 0x47, 0x00];
+        for window in stream.chunks_exact(188) {
+            writer.write_packet(window.try_into().unwrap()).unwrap();
+            dvb.extend_from_slice(window);
+            dvb.extend_from_slice(&[0; 16]);
+        }
+        assert_eq!(detect_packet_size(&writer.into_inner()), Some(192));
+        // the stray sync byte in the junk up front doesn't throw it
+        assert_eq!(detect_packet_size(&dvb), Some(204));
+
+        // too few packets to be sure of anything
+        assert_eq!(detect_packet_size(&stream[..188 * 4]), None);
+        assert_eq!(detect_packet_size(&[0x47; 100]), None);
+        assert_eq!(detect_packet_size(&[]), None);
+    }
+
+    #[test]
+    fn dvb_204_packets() {
+        let capture = sample_capture();
+        let mut dvb = Vec::new();
+        for window in capture.chunks_exact(188) {
+            dvb.extend_from_slice(window);
+            dvb.extend_from_slice(&[0xA5; 16]);
+        }
+        let first: &[u8; 204] = dvb[..204].try_into().unwrap();
+        assert_eq!(&Packet::from_204(first).data()[..], &capture[..188]);
+        assert_eq!(Packet::parity_bytes(first), &[0xA5; 16]);
+
+        // junk up front then the parity (no sync byte in it) strided over
+        let mut input = vec![0x00, 0x00];
+        input.extend_from_slice(&dvb);
+        let mut reader = PacketReader::with_packet_size(&input[..], 204).unwrap();
+        let mut read = Vec::new();
+        while let Some(data) = reader.next_packet().unwrap() {
+            read.extend_from_slice(&data);
+        }
+        assert_eq!(read, capture);
+        assert_eq!(reader.skipped(), 2);
+
+        let mut writer = TsWriter::new_m2ts(Vec::new(), 1_504_000);
+        for window in capture.chunks_exact(188) {
+            writer.write_packet(window.try_into().unwrap()).unwrap();
+        }
+        let m2ts = writer.into_inner();
+        let mut reader = PacketReader::with_packet_size(&m2ts[..], 192).unwrap();
+        let mut read = Vec::new();
+        while let Some(data) = reader.next_packet().unwrap() {
+            read.extend_from_slice(&data);
+        }
+        assert_eq!(read, capture);
+        assert_eq!(reader.skipped(), 0);
+
+        assert_eq!(
+            PacketReader::with_packet_size(&m2ts[..], 200).is_none(),
+            true
+        );
+    }
+
+    #[test]
+    fn continuity_gap_stats() {
+        let mut checker = ContinuityChecker::new();
+        for cc in [0, 1, 5, 6, 8].iter() {
+            let pkt = Packet::create_packet(false, false, false, 0x100, 0, 1, *cc);
+            checker.check(&Packet::new(pkt));
+        }
+        let clean = Packet::create_packet(false, false, false, 0x101, 0, 1, 0);
+        checker.check(&Packet::new(clean));
+
+        let stats = checker.stats();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(
+            stats[&0x100],
+            CcStats {
+                errors: 2,
+                max_gap: 3,
+                total_lost_estimate: 4
+            }
+        );
+    }
+
+    #[test]
+    fn section_editor_bumps_version() {
+        let pmt = Packet::new(create_pmt_packet(
+            0x1000,
+            &[(0x100, 0x1B), (0x101, 0x0F)],
+            0,
+        ));
+        let tables = pmt.tables().unwrap();
+        let original = tables.section_data();
+        let mut editor = SectionEditor::new(original).unwrap();
+        editor.set_version(original.version() + 1);
+        let edited = editor.finish();
+        let edited = edited.as_slice();
+
+        assert_eq!(edited.version(), 1);
+        assert_eq!(edited.current(), true);
+        assert_eq!(calc_crc32(edited), edited.crc32());
+        assert_eq!(edited.len(), original.len());
+        let crc_idx = original.len() - 4;
+        for idx in (0..crc_idx).filter(|idx| *idx != 5) {
+            assert_eq!(edited[idx], original[idx]);
+        }
+        assert_eq!(edited[5] & 0xC1, original[5] & 0xC1);
+        assert_eq!(pmt_content_eq(original, edited), true);
+
+        assert_eq!(SectionEditor::new(&original[..10]).is_none(), true);
+    }
+
+    #[test]
+    fn sample_pat_section_start() {
+        let pat = Packet::new(hex_to_bin(SAMPLE_PAT_HEX));
+        assert_eq!(pat.pid(), 0);
+        assert_eq!(pat.is_section_start(), true);
+        let continuation = Packet::create_packet(false, false, false, 0, 0, 1, 1);
+        assert_eq!(Packet::new(continuation).is_section_start(), false);
+        // PUSI without a payload starts nothing
+        let no_payload = Packet::create_packet(false, true, false, 0, 0, 2, 1);
+        assert_eq!(Packet::new(no_payload).is_section_start(), false);
+    }
+
+    #[test]
+    fn nominal_bitrate_between_pcrs() {
+        let mut buf = Vec::new();
+        // 100 packets across 10ms is 15.04Mbps, starting just short of the PCR wrap
+        let start = PCR_WRAP / 300 - 450;
+        let stuffing = Packet::create_packet(false, false, false, 0x200, 0, 1, 0);
+        let mut af = vec![0x10];
+        af.extend_from_slice(&pcr_bytes(start, 0));
+        buf.extend_from_slice(af_packet(&af).data());
+        for _ in 0..99 {
+            buf.extend_from_slice(&stuffing);
+        }
+        let mut af = vec![0x10];
+        af.extend_from_slice(&pcr_bytes((start + 900) % (PCR_WRAP / 300), 0));
+        buf.extend_from_slice(af_packet(&af).data());
+        buf.extend_from_slice(&stuffing);
+
+        let bitrate = nominal_bitrate(&buf, 256).unwrap();
+        assert_eq!((bitrate - 15_040_000.0).abs() < 1.0, true);
+        assert_eq!(nominal_bitrate(&buf, 0x200), None);
+    }
+
+    #[test]
+    fn packetize_pes_pads_final_packet() {
+        let mut pes = vec![0x00, 0x00, 0x01, 0xE0, 0x00, 0x00, 0x80, 0x00, 0x00];
+        pes.resize(300, 0xAB);
+        let packets = packetize_pes(0x100, &pes, 15);
+        assert_eq!(packets.len(), 2);
+
+        let first = Packet::new(packets[0]);
+        assert_eq!(first.pusi(), true);
+        assert_eq!(first.afc(), 1);
+        assert_eq!(first.cc(), 15);
+        assert_eq!(first.payload_data(), &pes[..184]);
+
+        let last = Packet::new(packets[1]);
+        assert_eq!(last.pusi(), false);
+        assert_eq!(last.afc(), 3);
+        assert_eq!(last.cc(), 0);
+        assert_eq!(last.aflen(), 67);
+        assert_eq!(last.has_pcr(), false);
+        assert_eq!(last.stuffing(), &[0xFF; 66][..]);
+        assert_eq!(last.payload_data(), &pes[184..]);
+
+        // an exact fit needs no adaptation field
+        let exact = packetize_pes(0x100, &pes[..184], 0);
+        assert_eq!(exact.len(), 1);
+        assert_eq!(Packet::new(exact[0]).afc(), 1);
+    }
+
+    #[test]
+    fn stuffing_only_adaptation_field() {
+        let af = stuffing_only_af(10);
+        assert_eq!(af.len(), 11);
+        assert_eq!(&af[..2], &[10, 0]);
+
+        let mut data = Packet::create_packet(false, false, false, 256, 0, 3, 0);
+        data[4..4 + af.len()].copy_from_slice(&af);
+        let pkt = Packet::new(data);
+        assert_eq!(pkt.is_discontinuity(), false);
+        assert_eq!(pkt.is_random_access(), false);
+        assert_eq!(pkt.es_priority(), false);
+        assert_eq!(pkt.has_pcr(), false);
+        assert_eq!(pkt.has_opcr(), false);
+        assert_eq!(pkt.has_splice_countdown(), false);
+        assert_eq!(pkt.has_transport_private_data(), false);
+        assert_eq!(pkt.has_extension(), false);
+        assert_eq!(pkt.stuffing(), &[0xFF; 9]);
+
+        // stuffing after a PCR
+        let mut af_body = vec![0x10];
+        af_body.extend_from_slice(&pcr_bytes(1, 0));
+        af_body.extend_from_slice(&[0xFF; 3]);
+        assert_eq!(af_packet(&af_body).stuffing(), &[0xFF; 3]);
+        assert_eq!(stuffing_only_af(0), vec![0]);
+    }
+
+    #[test]
+    fn pes_assembler_errors() {
+        let mut bounded = vec![0, 0, 1, 0xC0, 0x01, 0x26, 0x80, 0, 0];
+        bounded.resize(300, 0xAA);
+        let mut unbounded = vec![0, 0, 1, 0xE0, 0, 0, 0x80, 0, 0];
+        unbounded.resize(400, 0xBB);
+
+        let mut assembler = PesAssembler::new(0x100);
+        let packets = pes_packets(0x100, &bounded, 0);
+        assert_eq!(assembler.push(&packets[0]), Ok(None));
+        let unit = assembler.push(&packets[1]).unwrap().unwrap();
+        assert_eq!(unit.data, bounded);
+        assert_eq!(unit.payload().len(), 291);
+
+        // the start code of the next unit is corrupted
+        let mut corrupt = pes_packets(0x100, &bounded, 2);
+        let mut data = *corrupt[0].data();
+        data[4 + 2] = 0x02;
+        corrupt[0] = Packet::new(data);
+        assert_eq!(assembler.push(&corrupt[0]), Err(PesError::MissingStartCode));
+        // and its continuation is skipped rather than glued onto anything
+        assert_eq!(assembler.push(&corrupt[1]), Ok(None));
+
+        // unbounded units close when the next one starts
+        let video = pes_packets(0x100, &unbounded, 4);
+        assert_eq!(assembler.push(&video[0]), Ok(None));
+        assert_eq!(assembler.push(&video[1]), Ok(None));
+        assert_eq!(assembler.push(&video[2]), Ok(None));
+        let next = pes_packets(0x100, &unbounded, 7);
+        let unit = assembler.push(&next[0]).unwrap().unwrap();
+        // the last packet of the unbounded unit was stuffed out with an adaptation field
+        assert_eq!(unit.data, unbounded);
+
+        // losing a packet in the middle drops the unit
+        assert_eq!(
+            assembler.push(&next[2]),
+            Err(PesError::CcDiscontinuity {
+                expected: 8,
+                found: 9
+            })
+        );
+
+        // a bounded unit cut short by the next one starting
+        let mut assembler = PesAssembler::new(0x100);
+        let packets = pes_packets(0x100, &bounded, 0);
+        assembler.push(&packets[0]).unwrap();
+        let restart = pes_packets(0x100, &bounded, 1);
+        assert_eq!(
+            assembler.push(&restart[0]),
+            Err(PesError::Truncated {
+                declared: 300,
+                received: 184
+            })
+        );
+    }
+
+    #[test]
+    fn consecutive_unbounded_video() {
+        let mut first = vec![0, 0, 1, 0xE0, 0, 0, 0x80, 0, 0];
+        first.resize(368, 0x11);
+        let mut second = vec![0, 0, 1, 0xE0, 0, 0, 0x80, 0, 0];
+        second.resize(200, 0x22);
+        let mut packets = pes_packets(0x100, &first, 0);
+        packets.extend(pes_packets(0x100, &second, 2));
+        packets.extend(pes_packets(0x100, &first, 4));
+        assert_eq!(packets.len(), 6);
+
+        let mut assembler = PesAssembler::new(0x100);
+        assert_eq!(assembler.push(&packets[0]), Ok(None));
+        // exactly two full packets, nothing says the unit is over until the next PUSI
+        assert_eq!(assembler.push(&packets[1]), Ok(None));
+        let unit = assembler.push(&packets[2]).unwrap().unwrap();
+        assert_eq!(unit.data, first);
+        assert_eq!(assembler.push(&packets[3]), Ok(None));
+        let unit = assembler.push(&packets[4]).unwrap().unwrap();
+        assert_eq!(unit.data, second);
+    }
+
+    #[test]
+    fn seek_index_one_idr() {
+        // one packet of unbounded video PES per access unit with a PCR on each
+        fn video(es: &[u8], random_access: bool, pcr: u64, cc: u8) -> Packet {
+            let mut pes = vec![0, 0, 1, 0xE0, 0, 0, 0x80, 0, 0];
+            pes.extend_from_slice(es);
+            pes.resize(150, 0x55);
+            let header = HeaderFields {
+                tei: false,
+                pusi: true,
+                priority: false,
+                pid: 0x100,
+                tsc: 0,
+                afc: 3,
+                cc: cc,
+            };
+            let flags = if random_access { 0x50 } else { 0x10 };
+            let af = [flags, 0, 0, 0, 0, 0x7E, 0];
+            let data = Packet::create_packet_full(header, Some(&af), &pes).unwrap();
+            return Packet::new(Packet::with_pcr(data, pcr));
+        }
+        // AUD, SPS, PPS then the IDR slice
+        let idr = [
+            0, 0, 1, 0x09, 0xF0, 0, 0, 1, 0x67, 0x42, 0, 0, 1, 0x68, 0xCE, 0, 0, 1, 0x65, 0x88,
+        ];
+        let non_idr = [0, 0, 1, 0x09, 0xF0, 0, 0, 1, 0x41, 0x9A];
+        let audio = Packet::new(Packet::create_packet(false, true, false, 0x101, 0, 1, 0));
+        let packets = [
+            video(&non_idr, false, 27_000, 0),
+            audio,
+            video(&idr, true, 54_000, 1),
+            video(&non_idr, false, 81_000, 2),
+            // flagged but not starting on an IDR, so no seek point
+            video(&non_idr, true, 108_000, 3),
+            video(&non_idr, false, 135_000, 4),
+        ];
+        let mut builder = SeekIndexBuilder::new(0x100, 0x100, StreamType::H264);
+        for pkt in packets.iter() {
+            builder.push(pkt);
+        }
+        assert_eq!(
+            builder.points(),
+            &[SeekPoint {
+                pcr: Some(54_000),
+                byte_offset: 2 * 188,
+                is_idr: true,
+            }]
+        );
+
+        // H.265 CRA counts as a random access point, just not an IDR
+        let cra = [0, 0, 1, 0x46, 0x01, 0x10, 0, 0, 1, 0x2A, 0x01];
+        let mut builder = SeekIndexBuilder::new(0x100, 0x100, StreamType::H265);
+        builder.push(&video(&cra, true, 27_000, 0));
+        builder.push(&video(&cra, false, 54_000, 1));
+        assert_eq!(builder.points().len(), 1);
+        assert_eq!(builder.points()[0].is_idr, false);
+    }
+
+    #[test]
+    fn sample_table_ids() {
+        let table_ids = table_ids_by_pid(&sample_capture());
+        let mut expected = BTreeMap::new();
+        expected.insert(0, [0x00].iter().cloned().collect::<BTreeSet<u8>>());
+        expected.insert(0x1000, [0x02].iter().cloned().collect());
+        assert_eq!(table_ids, expected);
+    }
+
+    #[test]
+    fn sample_pat_header_fields() {
+        let pat = Packet::new(hex_to_bin(SAMPLE_PAT_HEX));
+        let fields = pat.fields();
+        assert_eq!(fields.tei, pat.tei());
+        assert_eq!(fields.pusi, pat.pusi());
+        assert_eq!(fields.priority, pat.priority());
+        assert_eq!(fields.pid, pat.pid());
+        assert_eq!(fields.tsc, pat.tsc());
+        assert_eq!(fields.afc, pat.afc());
+        assert_eq!(fields.cc, pat.cc());
+        // and they feed straight back into the builder
+        let rebuilt = Packet::create_packet_full(fields, None, pat.payload_data()).unwrap();
+        assert_eq!(&rebuilt[..], &pat.data()[..]);
+    }
+
+    #[test]
+    fn builders_round_trip() {
+        assert_roundtrip_pat(&[0x1000]);
+        assert_roundtrip_pat(&[0x100, 0x200, 0x300]);
+        assert_roundtrip_pmt(0x1000, &[]);
+        assert_roundtrip_pmt(0x1000, &[(0x100, 0x1B), (0x101, 0x0F), (0x102, 0x06)]);
+
+        let mut bad_crc = create_pmt_packet(0x1000, &[(0x100, 0x1B)], 0);
+        bad_crc[187] ^= 0x01;
+        assert_eq!(roundtrip_ok(&bad_crc), false);
+        let no_pusi = Packet::create_packet(false, false, false, 0, 0, 1, 0);
+        assert_eq!(roundtrip_ok(&no_pusi), false);
+    }
+
+    #[test]
+    fn extraction_keeps_ca_pids() {
+        // CA_system_ID 0x0B00 with its ECM on PID 0x1FF0
+        let ecm_descriptor = [0x09, 0x04, 0x0B, 0x00, 0xFF, 0xF0];
+        let streams: [(u16, u8, &[u8]); 2] = [(0x100, 0x1B, &ecm_descriptor), (0x101, 0x0F, &[])];
+        let pmt_packets = create_pmt_packet_with_descriptors(0x1000, 1, 0x100, &[], &streams, 0);
+        let pmt = Packet::new(pmt_packets[0]);
+        let tables = pmt.tables().unwrap();
+        let section = tables.section_data();
+        let mut extractor = ProgramExtractor::from_pmt(0x1000, section.table_data());
+        assert_eq!(extractor.wants(0x1FF0), true);
+
+        let cat = long_section(0x01, 0xFFFF, 0, 0, &[0x09, 0x04, 0x0B, 0x00, 0xE1, 0x50]);
+        extractor.add_cat((&cat[..]).table_data());
+        assert_eq!(
+            extractor.pids(),
+            vec![0, CAT_PID, 0x100, 0x101, 0x150, 0x1000, 0x1FF0]
+        );
+
+        let mut mux = Vec::new();
+        for pid in [0x1FF0, 0x1FF1, 0x150].iter() {
+            mux.extend_from_slice(&Packet::create_packet(false, false, false, *pid, 0, 1, 0));
+        }
+        let extracted = extractor.extract(&mux);
+        assert_eq!(
+            pid_set(&extracted).into_iter().collect::<Vec<u16>>(),
+            vec![0x150, 0x1FF0]
+        );
+    }
+
+    #[test]
+    fn synth_pmt_with_ca() {
+        let streams = [(0x100, 0x1B, Some((0x0B00, 0x500))), (0x101, 0x0F, None)];
+        let pmt_packets =
+            create_pmt_packet_with_ca(0x1000, 1, 0x100, Some((0x0B00, 0x501)), &streams, 0);
+        assert_eq!(pmt_packets.len(), 1);
+        let pmt = Packet::new(pmt_packets[0]);
+        let tables = pmt.tables().unwrap();
+        let section = tables.section_data();
+        let table = section.table_data();
+        assert_eq!(calc_crc32(section), section.crc32());
+
+        let program_ca: Vec<Descriptor> = descriptors(table.descriptor_data().unwrap()).collect();
+        assert_eq!(program_ca.len(), 1);
+        assert_eq!(program_ca[0].ca_pid(), Some(0x501));
+
+        let video = table.elementary_streams();
+        let video_ca: Vec<Descriptor> = descriptors(video.es_info()).collect();
+        assert_eq!(video_ca.len(), 1);
+        assert_eq!(video_ca[0].ca_system_id(), Some(0x0B00));
+        assert_eq!(video_ca[0].ca_pid(), Some(0x500));
+        let audio = video.next_stream().unwrap();
+        assert_eq!(audio.stream_pid(), 0x101);
+        assert_eq!(audio.es_info_len(), 0);
+
+        let extractor = ProgramExtractor::from_pmt(0x1000, table);
+        assert_eq!(extractor.wants(0x500), true);
+        assert_eq!(extractor.wants(0x501), true);
+    }
+
+    #[test]
+    fn aac_framing() {
+        let adts = [0xFF, 0xF1, 0x50, 0x80, 0x2E, 0x7F, 0xFC];
+        assert_eq!(detect_aac_framing(&adts), AacFraming::Adts);
+        // syncword then a 13 bit audioMuxLengthBytes of 0x30
+        let loas = [0x56, 0xE0, 0x30, 0x47, 0xFC];
+        assert_eq!(detect_aac_framing(&loas), AacFraming::Loas);
+        assert_eq!(detect_aac_framing(&[0, 0, 1, 0xC0]), AacFraming::Unknown);
+        assert_eq!(detect_aac_framing(&[0xFF]), AacFraming::Unknown);
+
+        let latm_pes = [0, 0, 1, 0xC0, 0, 0, 0x84, 0, 0, 0x56, 0xE0, 0x30];
+        assert_eq!(check_alignment(&latm_pes, StreamType::LatmAac), Ok(()));
+        assert_eq!(
+            check_alignment(&latm_pes, StreamType::AdtsAac),
+            Err(PesError::MisalignedPes { stream_id: 0xC0 })
+        );
+    }
+
+    #[test]
+    fn visit_packets() {
+        let capture = sample_capture();
+        let mut count = 0;
+        for_each_packet(&capture, |_| count += 1);
+        assert_eq!(count, capture.chunks_exact(188).count());
+
+        // junk between packets is skipped
+        let mut noisy = capture[..188].to_vec();
+        noisy.extend_from_slice(&[0x00, 0x12, 0x34]);
+        noisy.extend_from_slice(&capture[188..]);
+        let mut pids = Vec::new();
+        for_each_packet(&noisy, |pkt| pids.push(pkt.pid()));
+        assert_eq!(pids, vec![0, 0x1000, 0x100]);
+
+        let result = try_for_each_packet(&capture, |pkt| {
+            if pkt.pid() == 0x1000 {
+                return Err(pkt.pid());
+            }
+            return Ok(());
+        });
+        assert_eq!(result, Err(0x1000));
+    }
+
+    #[test]
+    fn payload_offsets() {
+        let payload_only = Packet::create_packet(false, false, false, 256, 0, 1, 0);
+        assert_eq!(Packet::new(payload_only).payload_offset(), Some(4));
+
+        let with_af =
+            Packet::create_packet_with_payload(false, false, false, 256, 0, 3, 0, &[1; 100]);
+        let with_af = Packet::new(with_af.unwrap());
+        assert_eq!(with_af.aflen(), 83);
+        assert_eq!(with_af.payload_offset(), Some(88));
+        assert_eq!(with_af.data()[88..], [1; 100][..]);
+
+        let af_only = Packet::create_packet_with_payload(false, false, false, 256, 0, 2, 0, &[]);
+        assert_eq!(Packet::new(af_only.unwrap()).payload_offset(), None);
+    }
+
+    // splice_info_section around a command, CRC left as zeros since the parser doesn't check it
+    fn splice_info_section(command_type: u8, command: &[u8]) -> Vec<u8> {
+        let section_len = 11 + command.len() + 2 + 4;
+        let mut section = vec![0xFC, 0x30 | (section_len >> 8) as u8, section_len as u8];
+        // protocol_version, not encrypted with a pts_adjustment of 0, cw_index
+        section.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0xFF]);
+        // tier 0xFFF and the command length
+        section.extend_from_slice(&[0xFF, 0xF0 | (command.len() >> 8) as u8, command.len() as u8]);
+        section.push(command_type);
+        section.extend_from_slice(command);
+        section.extend_from_slice(&[0, 0]); // no descriptors
+        section.extend_from_slice(&[0; 4]);
+        return section;
+    }
+
+    #[test]
+    fn splice_insert_immediate() {
+        // event 0x1234, out of network, program splice, immediate, no break_duration
+        let command = [0, 0, 0x12, 0x34, 0x7F, 0xDF, 0x00, 0x01, 0x02, 0x03];
+        let insert = parse_splice_insert(&splice_info_section(0x05, &command)).unwrap();
+        assert_eq!(insert.splice_event_id, 0x1234);
+        assert_eq!(insert.cancel, false);
+        assert_eq!(insert.out_of_network, true);
+        assert_eq!(insert.immediate, true);
+        assert_eq!(insert.pts_time, None);
+        assert_eq!(insert.duration, None);
+        assert_eq!(insert.unique_program_id, 1);
+        assert_eq!(insert.avail_num, 2);
+        assert_eq!(insert.avails_expected, 3);
+    }
+
+    #[test]
+    fn splice_insert_timed() {
+        // pts_time 0x1_0000_0000 + 90000 and a 30 second auto return break
+        let pts_time: u64 = (1 << 32) + 90_000;
+        let duration: u64 = 30 * 90_000;
+        let mut command = vec![0, 0, 0, 0x01, 0x7F, 0xEF];
+        command.push(0xFE | (pts_time >> 32) as u8);
+        command.extend_from_slice(&(pts_time as u32).to_be_bytes());
+        command.push(0xFE | (duration >> 32) as u8);
+        command.extend_from_slice(&(duration as u32).to_be_bytes());
+        command.extend_from_slice(&[0x00, 0x05, 0x00, 0x00]);
+        let insert = parse_splice_insert(&splice_info_section(0x05, &command)).unwrap();
+        assert_eq!(insert.immediate, false);
+        assert_eq!(insert.pts_time, Some(pts_time));
+        assert_eq!(insert.auto_return, true);
+        assert_eq!(insert.duration, Some(duration));
+        assert_eq!(insert.unique_program_id, 5);
+
+        // time_specified_flag clear gives no pts_time even when not immediate
+        let command = [0, 0, 0, 0x02, 0x7F, 0xCF, 0x7F, 0, 0, 0, 0];
+        let insert = parse_splice_insert(&splice_info_section(0x05, &command)).unwrap();
+        assert_eq!(insert.pts_time, None);
+        assert_eq!(
+            parse_splice_insert(&splice_info_section(0x06, &[0xFE])),
+            None
+        );
+    }
+
+    #[test]
+    fn gop_interval_from_rai() {
+        // PCR every 10 packets 1ms apart, random access every 50 packets so every 5ms
+        let mut buf = Vec::new();
+        for n in 0..500u64 {
+            let mut flags = 0;
+            if n % 10 == 0 {
+                flags |= 0x10;
+            }
+            if n % 50 == 5 {
+                flags |= 0x40;
+            }
+            if flags == 0 {
+                buf.extend_from_slice(&Packet::create_packet(false, false, false, 256, 0, 1, 0));
+                continue;
+            }
+            let mut af = vec![flags];
+            if flags & 0x10 != 0 {
+                af.extend_from_slice(&pcr_bytes(n / 10 * 90, 0));
+            }
+            buf.extend_from_slice(af_packet(&af).data());
+        }
+        let stats = gop_stats(&buf, 256, 256).unwrap();
+        assert_eq!(stats.count, 10);
+        assert_eq!((stats.avg_ms - 5.0).abs() < 1e-9, true);
+        assert_eq!((stats.max_ms - 5.0).abs() < 1e-9, true);
+        assert_eq!(gop_stats(&buf, 256, 0x101), None);
+    }
+
+    #[test]
+    fn checked_create_packet() {
+        assert_eq!(
+            Packet::try_create_packet(false, true, false, 0x2000, 0, 1, 0),
+            Err(PacketError::OutOfRange {
+                field: "pid",
+                value: 0x2000
+            })
+        );
+        assert_eq!(
+            Packet::try_create_packet(false, true, false, 0x100, 0, 1, 16),
+            Err(PacketError::OutOfRange {
+                field: "cc",
+                value: 16
+            })
+        );
+        assert_eq!(
+            Packet::try_create_packet(false, true, false, 0x100, 4, 1, 0),
+            Err(PacketError::OutOfRange {
+                field: "tsc",
+                value: 4
+            })
+        );
+        assert_eq!(
+            Packet::try_create_packet(false, true, false, 0x1FFF, 0, 1, 15),
+            Ok(Packet::create_packet(false, true, false, 0x1FFF, 0, 1, 15))
+        );
+    }
+
+    #[test]
+    fn segment_long_eit() {
+        // EIT schedule header for service 1, version 2 and current, then 1500 bytes of body
+        let mut payload = vec![0x50, 0xF0, 0, 0, 1, 0xC5, 0, 0];
+        let body: Vec<u8> = (0..1500).map(|n| n as u8).collect();
+        payload.extend_from_slice(&body);
+        let sections = segment_table(&payload, 1024);
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0].len(), 1024);
+        assert_eq!(sections[1].len(), 12 + 1500 - 1012);
+
+        let mut collector = TableCollector::new(0x50);
+        assert_eq!(collector.add_section(&sections[1]), false);
+        assert_eq!(collector.add_section(&sections[0]), true);
+        let mut reassembled = Vec::new();
+        for (n, section) in collector.sections().iter().enumerate() {
+            assert_eq!(section.section_num(), n as u8);
+            assert_eq!(section.last_section_num(), 1);
+            assert_eq!(section.version(), 2);
+            assert_eq!(section.section_length() as usize, section.len() - 3);
+            reassembled.extend_from_slice(&section[8..section.len() - 4]);
+        }
+        assert_eq!(reassembled, body);
+
+        assert_eq!(segment_table(&payload, 12).is_empty(), true);
+    }
+
+    #[test]
+    fn diff_cc_change() {
+        let pkt = Packet::create_packet(false, true, false, 0x100, 0, 1, 3);
+        let changed = Packet::with_cc(pkt, 4);
+        let diff = packet_diff(&pkt, &changed);
+        assert_eq!(diff, vec![(3, 0x13, 0x14)]);
+        assert_eq!(format!("{}", PacketDiff(&diff)), "  3: 13 -> 14");
+        assert_eq!(packet_diff(&pkt, &pkt).is_empty(), true);
+        assert_eq!(format!("{}", PacketDiff(&[])), "packets identical");
+    }
+
+    #[test]
+    fn reclaim_reserved_null() {
+        let mut af = vec![0x10];
+        af.extend_from_slice(&[0; 6]);
+        let header = HeaderFields {
+            tei: false,
+            pusi: false,
+            priority: false,
+            pid: 0x1FFF,
+            tsc: 0,
+            afc: 2,
+            cc: 7,
+        };
+        let reserved = Packet::create_packet_full(header, Some(&af), &[]).unwrap();
+        assert_eq!(Packet::new(reserved).is_reserved_null_for_pcr(), true);
+        let plain_null = Packet::create_packet(false, false, false, 0x1FFF, 0, 1, 0);
+        assert_eq!(Packet::new(plain_null).is_reserved_null_for_pcr(), false);
+        assert_eq!(
+            Packet::reclaim_null_for_pcr(plain_null, 0x100, 1, 2),
+            plain_null
+        );
+
+        let carrier = Packet::new(Packet::reclaim_null_for_pcr(reserved, 0x100, 900_000, 123));
+        assert_eq!(carrier.pid(), 0x100);
+        assert_eq!(carrier.afc(), 2);
+        assert_eq!(carrier.cc(), 7);
+        assert_eq!(carrier.aflen(), 183);
+        assert_eq!(carrier.has_pcr(), true);
+        assert_eq!(carrier.pcr(), 900_000 * 300 + 123);
+    }
+
+    #[test]
+    fn private_stream_kinds() {
+        // 0x06 on PID 0x101 with a subtitling descriptor for one english subtitle
+        let subtitle: &[u8] = &[
+            0x06, 0xE1, 0x01, 0xF0, 10, 0x59, 8, b'e', b'n', b'g', 0x10, 0, 1, 0, 1,
+        ];
+        assert_eq!(
+            resolve_private_stream(subtitle),
+            PrivateStreamKind::Subtitle
+        );
+        let teletext: &[u8] = &[
+            0x06, 0xE1, 0x02, 0xF0, 7, 0x56, 5, b'e', b'n', b'g', 0x09, 0,
+        ];
+        assert_eq!(
+            resolve_private_stream(teletext),
+            PrivateStreamKind::Teletext
+        );
+        let registered: &[u8] = &[0x06, 0xE1, 0x03, 0xF0, 6, 0x05, 4, b'K', b'L', b'V', b'A'];
+        assert_eq!(
+            resolve_private_stream(registered),
+            PrivateStreamKind::Registered(StreamType::Klva)
+        );
+        let bare: &[u8] = &[0x06, 0xE1, 0x04, 0xF0, 0];
+        assert_eq!(resolve_private_stream(bare), PrivateStreamKind::Unknown);
+        let video: &[u8] = &[
+            0x1B, 0xE1, 0x05, 0xF0, 7, 0x56, 5, b'e', b'n', b'g', 0x09, 0,
+        ];
+        assert_eq!(resolve_private_stream(video), PrivateStreamKind::Unknown);
+    }
+
+    #[test]
+    fn pcr_for_every_packet() {
+        let plain = || Packet::new(Packet::create_packet(false, false, false, 256, 0, 1, 0));
+        let mut packets = vec![plain()];
+        let mut af = vec![0x10];
+        af.extend_from_slice(&pcr_bytes(900, 0));
+        packets.push(af_packet(&af));
+        packets.extend((0..3).map(|_| plain()));
+        let mut af = vec![0x10];
+        af.extend_from_slice(&pcr_bytes(1300, 0));
+        packets.push(af_packet(&af));
+        packets.push(plain());
+
+        let timed: Vec<u64> = packets
+            .into_iter()
+            .with_pcr(256)
+            .map(|(pcr, _)| pcr)
+            .collect();
+        // 400 base ticks over 4 packets, extrapolated one packet either side
+        assert_eq!(
+            timed,
+            vec![
+                800 * 300,
+                900 * 300,
+                1000 * 300,
+                1100 * 300,
+                1200 * 300,
+                1300 * 300,
+                1400 * 300
+            ]
+        );
+        // the middle packet between the two PCRs lands halfway
+        assert_eq!(timed[3], (900 + 1300) / 2 * 300);
+
+        let untimed = vec![plain(), plain()];
+        assert_eq!(untimed.into_iter().with_pcr(256).count(), 0);
+    }
+
+    #[test]
+    fn set_odd_key_parity() {
+        let pkt = Packet::create_packet(false, false, false, 256, 0, 3, 9);
+        let odd = Packet::new(Packet::set_scrambling(pkt, ScramblingControl::OddKey));
+        assert_eq!(odd.scrambling(), ScramblingControl::OddKey);
+        assert_eq!(odd.afc(), 3);
+        assert_eq!(odd.cc(), 9);
+        let even = Packet::new(Packet::set_scrambling(
+            *odd.data(),
+            ScramblingControl::EvenKey,
+        ));
+        assert_eq!(even.scrambling(), ScramblingControl::EvenKey);
+        let clear = Packet::new(Packet::clear_scrambling(*even.data()));
+        assert_eq!(clear.scrambling(), ScramblingControl::Clear);
+        assert_eq!(clear.data(), &pkt);
+    }
+
+    #[test]
+    fn es_loop_stops_before_crc() {
+        let pkt = Packet::new(create_pmt_packet(0x1000, &[(256, 27)], 0));
+        let tables = pkt.tables().unwrap();
+        let section = tables.section_data();
+        // reading the loop straight off the section bytes runs into the CRC
+        let raw = &section[8..];
+        let unbounded = raw.elementary_streams();
+        assert_eq!(unbounded.len(), 5 + 4);
+        let es = elementary_streams_bounded(section);
+        assert_eq!(es.len(), 5);
+        assert_eq!(es.stream_pid(), 256);
+        assert_eq!(es.next_stream(), None);
+        let pmt = section.table_data();
+        assert_eq!(es, pmt.elementary_streams());
+        assert_eq!(elementary_streams_bounded(&section[..10]).is_empty(), true);
+    }
+
+    #[test]
+    fn extract_second_program() {
+        let stream = create_two_program_stream();
+        assert_eq!(validate_stream(&stream).is_clean(), true);
+        let programs = find_pat(&stream).unwrap();
+        assert_eq!(programs, vec![(1, 0x1000), (2, 0x1100)]);
+
+        // the PMT of program 2
+        let pmt_pkt = stream
+            .chunks_exact(188)
+            .map(|window| Packet::new(window.try_into().unwrap()))
+            .find(|pkt| pkt.pid() == 0x1100)
+            .unwrap();
+        let tables = pmt_pkt.tables().unwrap();
+        let section = tables.section_data();
+        let mut extractor = ProgramExtractor::from_pmt(0x1100, section.table_data());
+        extractor.rewrite_pat(2);
+        let out = extractor.extract(&stream);
+
+        assert_eq!(
+            pid_set(&out),
+            [0, 0x200, 0x201, 0x1100].iter().cloned().collect()
+        );
+        assert_eq!(find_pat(&out).unwrap(), vec![(2, 0x1100)]);
+        assert_eq!(validate_stream(&out).is_clean(), true);
+        assert_eq!(out.len(), 188 * (1 + 1 + 8));
+    }
+
+    #[test]
+    fn describe_two_programs() {
+        let description = describe_stream(&create_two_program_stream());
+        assert_eq!(description.programs.len(), 2);
+        let first = &description.programs[0];
+        assert_eq!((first.program_num, first.pmt_pid), (1, 0x1000));
+        assert_eq!(first.pcr_pid, Some(0x100));
+        assert_eq!(
+            first.streams,
+            vec![
+                (0x100, StreamType::H264, None),
+                (0x101, StreamType::AdtsAac, None)
+            ]
+        );
+        assert_eq!(description.programs[1].pcr_pid, Some(0x200));
+        assert_eq!(
+            description.to_string(),
+            "Program 1 (PMT 0x1000, PCR 0x0100)\n  Stream 0x0100: H264\n  Stream 0x0101: AdtsAac\n\
+             Program 2 (PMT 0x1100, PCR 0x0200)\n  Stream 0x0200: H264\n  Stream 0x0201: AdtsAac"
+        );
+
+        // languages come from the ISO 639 descriptor, a PMT that never arrives is noted
+        let language = [0x0A, 0x04, b'e', b'n', b'g', 0x00];
+        let streams: [(u16, u8, &[u8]); 1] = [(0x101, 0x0F, &language)];
+        let mut stream =
+            create_pat_packet_with_programs(1, &[(1, 0x1000), (2, 0x1100)], 0)[0].to_vec();
+        stream.extend_from_slice(
+            &create_pmt_packet_with_descriptors(0x1000, 1, 0x1FFF, &[], &streams, 0)[0],
+        );
+        let description = describe_stream(&stream);
+        assert_eq!(
+            description.programs[0].streams,
+            vec![(0x101, StreamType::AdtsAac, Some(*b"eng"))]
+        );
+        assert_eq!(
+            description.to_string(),
+            "Program 1 (PMT 0x1000, no PCR)\n  Stream 0x0101: AdtsAac (eng)\n\
+             Program 2 (PMT 0x1100, PMT not found)"
+        );
+        assert_eq!(describe_stream(&[]).to_string(), "no programs");
+    }
+
+    #[test]
+    fn describe_interleaved_pmt() {
+        let description = describe_stream(&interleaved_pmt_capture());
+        assert_eq!(description.programs.len(), 1);
+        assert_eq!(description.programs[0].pmt_found, true);
+        assert_eq!(
+            description.to_string(),
+            "Program 1 (PMT 0x1000, PCR 0x0100)\n  Stream 0x0100: H264"
+        );
+    }
+
+    #[test]
+    fn fast_forward_trick_mode() {
+        // PTS then the trick mode byte: fast forward, field_id 2, intra slice refresh,
+        // frequency_truncation 1. PES_scrambling_control 01
+        let mut pes = vec![0, 0, 1, 0xE0, 0, 0, 0x90, 0x88, 6, 0x21, 0, 1, 0, 1, 0x15];
+        pes.extend_from_slice(&[0; 8]);
+        let header: &[u8] = &pes;
+        assert_eq!(header.pts(), Some(0));
+        assert_eq!(
+            header.trick_mode(),
+            Some(TrickMode::FastForward {
+                field_id: 2,
+                intra_slice_refresh: true,
+                frequency_truncation: 1
+            })
+        );
+        assert_eq!(
+            header.scrambling_control(),
+            PesScramblingControl::UserDefined(1)
+        );
+        pes[14] = 0x2A; // slow motion, rep_cntrl 10
+        let header: &[u8] = &pes;
+        assert_eq!(
+            header.trick_mode(),
+            Some(TrickMode::SlowMotion { rep_cntrl: 10 })
+        );
+        pes[7] = 0x80;
+        let header: &[u8] = &pes;
+        assert_eq!(header.trick_mode(), None);
+    }
+
+    #[test]
+    fn payload_efficiency_per_pid() {
+        let full = Packet::new(Packet::create_packet(false, false, false, 256, 0, 1, 0));
+        assert_eq!(full.payload_len(), 184);
+        assert_eq!((full.payload_efficiency() - 1.0).abs() < 1e-6, true);
+        let mostly_stuffing =
+            Packet::create_packet_with_payload(false, false, false, 257, 0, 3, 0, &[0xAA; 18])
+                .unwrap();
+        let mostly_stuffing = Packet::new(mostly_stuffing);
+        assert_eq!(mostly_stuffing.payload_len(), 18);
+        assert_eq!(mostly_stuffing.payload_efficiency() < 0.1, true);
+        let af_only = Packet::create_packet(false, false, false, 257, 0, 2, 0);
+        assert_eq!(Packet::new(af_only).payload_efficiency(), 0.0);
+
+        let mut buf = Vec::new();
+        buf.extend_from_slice(full.data());
+        buf.extend_from_slice(full.data());
+        buf.extend_from_slice(mostly_stuffing.data());
+        buf.extend_from_slice(&af_only);
+        let stats = pid_stats(&buf);
+        assert_eq!(stats[&256].packets, 2);
+        assert_eq!((stats[&256].avg_efficiency - 1.0).abs() < 1e-6, true);
+        assert_eq!(stats[&257].payload_bytes, 18);
+        assert_eq!(
+            (stats[&257].avg_efficiency - 9.0 / 184.0).abs() < 1e-6,
+            true
+        );
+    }
+
+    #[test]
+    fn version_bumped_on_change() {
+        let pmt = |pairs: &[(u16, u8)]| {
+            let pkt = Packet::new(create_pmt_packet(0x1000, pairs, 0));
+            let tables = pkt.tables().unwrap();
+            return tables.section_data().to_vec();
+        };
+        let mut updater = TableUpdater::new();
+        assert_eq!(updater.version(), None);
+        let first = updater.update(&pmt(&[(256, 27), (257, 15)])).unwrap();
+        assert_eq!(updater.version(), Some(0));
+        let again = updater.update(&pmt(&[(256, 27), (257, 15)])).unwrap();
+        assert_eq!(again, first);
+        assert_eq!(updater.version(), Some(0));
+
+        let changed = updater.update(&pmt(&[(256, 27), (258, 15)])).unwrap();
+        let changed_section: &[u8] = &changed;
+        assert_eq!(changed_section.version(), 1);
+        assert_eq!(calc_crc32(changed_section), changed_section.crc32());
+        assert_eq!(updater.version(), Some(1));
+        // the same changed PMT again stays on version 1
+        updater.update(&pmt(&[(256, 27), (258, 15)])).unwrap();
+        assert_eq!(updater.version(), Some(1));
+    }
+
+    #[test]
+    fn pcr_pid_of_first_program() {
+        assert_eq!(detect_pcr_pid(&create_two_program_stream()), Some(0x100));
+        assert_eq!(detect_pcr_pid(&sample_capture()), Some(256));
+        assert_eq!(detect_pcr_pid(&hex_to_bin(SAMPLE_PAT_HEX)), None);
+    }
+
+    #[test]
+    fn pcr_pid_with_shared_pmt_pid() {
+        // both programs' PMTs on 0x1000, program 2's going out first
+        let pmt = |program_num: u16, pcr_pid: u16| {
+            let body = [
+                0xE0 | (pcr_pid >> 8) as u8,
+                pcr_pid as u8,
+                0xF0,
+                0x00,
+                0x1B,
+                0xE0 | (pcr_pid >> 8) as u8,
+                pcr_pid as u8,
+                0xF0,
+                0x00,
+            ];
+            return long_section(2, program_num, 0, 0, &body);
+        };
+        let mut capture = Vec::new();
+        capture.extend_from_slice(&psi_packet(
+            0,
+            &pat_section(0, 0, &[(1, 0x1000), (2, 0x1000)]),
+            0,
+        ));
+        capture.extend_from_slice(&psi_packet(0x1000, &pmt(2, 0x200), 0));
+        capture.extend_from_slice(&psi_packet(0x1000, &pmt(1, 0x100), 1));
+        assert_eq!(detect_pcr_pid(&capture), Some(0x100));
+    }
+
+    #[test]
+    fn section_length_matches_structure() {
+        let pkt = Packet::new(create_pmt_packet(0x1000, &[(256, 27), (257, 15)], 0));
+        let tables = pkt.tables().unwrap();
+        assert_eq!(verify_section_length(tables), true);
+        let pat = Packet::new(create_pat_packet(&[0x1000], 0));
+        assert_eq!(verify_section_length(pat.tables().unwrap()), true);
+
+        let registration: &[u8] = &[0x05, 4, b'C', b'U', b'E', b'I'];
+        let streams: [(u16, u8, &[u8]); 2] = [(256, 27, &[]), (258, 0x86, registration)];
+        let described =
+            create_pmt_packet_with_descriptors(0x1000, 1, 256, registration, &streams, 0);
+        let described = Packet::new(described[0]);
+        assert_eq!(verify_section_length(described.tables().unwrap()), true);
+
+        // with stuffing after it so a longer section_length still fits in the buffer
+        let mut wrong = tables.section_data().to_vec();
+        wrong.extend_from_slice(&[0xFF; 8]);
+        wrong[2] += 1;
+        assert_eq!(verify_section_length(&wrong), false);
+        wrong[2] -= 3;
+        assert_eq!(verify_section_length(&wrong), false);
+        wrong[2] += 2;
+        assert_eq!(verify_section_length(&wrong), true);
+    }
+
+    #[test]
+    fn pcr_every_40ms() {
+        // 3.76Mbps is 2500 packets a second, so 100 packets every 40ms
+        let scheduler = PcrScheduler::new(3_760_000, 40);
+        assert_eq!(scheduler.spacing(), 100);
+        assert_eq!(scheduler.pcr_for_packet(0), Some((0, 0)));
+        assert_eq!(scheduler.pcr_for_packet(1), None);
+        assert_eq!(scheduler.pcr_for_packet(99), None);
+        let pcrs: Vec<u64> = (0..1000)
+            .filter_map(|index| scheduler.pcr_for_packet(index))
+            .map(|(base, ext)| base * 300 + ext as u64)
+            .collect();
+        assert_eq!(pcrs.len(), 10);
+        for pair in pcrs.windows(2) {
+            assert_eq!(pair[1] > pair[0], true);
+            // 40ms of the 27MHz clock
+            assert_eq!(pair[1] - pair[0], 1_080_000);
+        }
+    }
+
+    #[test]
+    fn validated_section_walk() {
+        let first = pat_section(0, 1, &[(1, 0x1000)]);
+        let second = pat_section(1, 1, &[(2, 0x1100)]);
+        let mut both = first.clone();
+        both.extend_from_slice(&second);
+        let sections: &[u8] = &both;
+        assert_eq!(sections.next_validated(), Some(second.as_slice()));
+
+        let crc_idx = first.len() - 1;
+        both[crc_idx] ^= 0xFF;
+        let sections: &[u8] = &both;
+        assert_eq!(sections.next(), Some(second.as_slice()));
+        assert_eq!(sections.next_validated(), None);
+    }
+
+    #[test]
+    fn pmt_without_pcr() {
+        let filler = Packet::new(create_pmt_packet(0x1000, &[(256, 27)], 0));
+        let tables = filler.tables().unwrap();
+        let section = tables.section_data();
+        let pmt = section.table_data();
+        assert_eq!(pmt.pcr_pid(), 0x1FFF);
+        assert_eq!(pmt.has_pcr_pid(), false);
+
+        let streams: [(u16, u8, &[u8]); 1] = [(256, 27, &[])];
+        let real = create_pmt_packet_with_descriptors(0x1000, 1, 256, &[], &streams, 0);
+        let real = Packet::new(real[0]);
+        let tables = real.tables().unwrap();
+        let section = tables.section_data();
+        let pmt = section.table_data();
+        assert_eq!(pmt.has_pcr_pid(), true);
+    }
+
+    #[test]
+    fn smpte_2038_anc_packet() {
+        // (value, bits) of one luma anc_packet on line 9 carrying a CEA-708 DID/SDID with
+        // parity and three user data words, then its checksum
+        let fields: &[(u16, usize)] = &[
+            (0, 6),
+            (0, 1),
+            (9, 11),
+            (0, 12),
+            (0x161, 10),
+            (0x101, 10),
+            (0x203, 10),
+            (0x296, 10),
+            (0x269, 10),
+            (0x152, 10),
+            (0x1AB, 10),
+        ];
+        let mut bits = Vec::new();
+        for (value, len) in fields.iter() {
+            bits.extend((0..*len).rev().map(|n| (value >> n) & 1 == 1));
+        }
+        while bits.len() % 8 != 0 {
+            bits.push(true);
+        }
+        let mut payload: Vec<u8> = bits
+            .chunks(8)
+            .map(|byte| byte.iter().fold(0, |acc, bit| (acc << 1) | *bit as u8))
+            .collect();
+        payload.extend_from_slice(&[0xFF; 4]);
+
+        let packets: Vec<AncPacket> = anc_packets(&payload).collect();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].c_not_y_channel, false);
+        assert_eq!(packets[0].line_number, 9);
+        assert_eq!(packets[0].did, 0x61);
+        assert_eq!(packets[0].sdid, 0x01);
+        assert_eq!(packets[0].data_count, 3);
+        assert_eq!(packets[0].user_data_words, vec![0x296, 0x269, 0x152]);
+        assert_eq!(packets[0].checksum_word, 0x1AB);
+        // cut off partway through the user data
+        assert_eq!(anc_packets(&payload[..8]).count(), 0);
+    }
+
+    #[test]
+    fn pcr_accuracy_flagged() {
+        // PCR every 10th packet 27000 ticks apart, the last one 17 ticks (~630ns) late
+        let packets: Vec<Packet> = (0..51u64)
+            .map(|n| {
+                if n % 10 != 0 {
+                    return Packet::new(Packet::create_packet(false, false, false, 256, 0, 1, 0));
+                }
+                let pcr = n * 2_700 + if n == 50 { 17 } else { 0 };
+                let mut af = vec![0x10];
+                af.extend_from_slice(&pcr_bytes(pcr / 300, (pcr % 300) as u16));
+                return af_packet(&af);
+            })
+            .collect();
+        let mut analyzer = PcrAnalyzer::new(256);
+        let mut lenient = PcrAnalyzer::with_tolerance_ns(256, 1_000);
+        for pkt in packets.iter() {
+            analyzer.push(pkt);
+            lenient.push(pkt);
+        }
+        let report = analyzer.report();
+        assert_eq!(report.checked, 4);
+        assert_eq!(report.out_of_tolerance, 1);
+        assert_eq!(
+            report.worst,
+            Some(PcrOffender {
+                packet: 50,
+                error_ns: 629
+            })
+        );
+        assert_eq!(report.overall_jitter_ns, 629);
+        assert_eq!(lenient.report().out_of_tolerance, 0);
+        assert_eq!(lenient.report().worst, None);
+    }
+
+    #[test]
+    fn checked_pmt_builder() {
+        assert_eq!(
+            try_create_pmt_packet(0x1000, &[(256, 27), (256, 15)], 0),
+            Err(PmtError::DuplicatePid(256))
+        );
+        assert_eq!(
+            try_create_pmt_packet(0x1000, &[(256, 27), (0x1FFF, 15)], 0),
+            Err(PmtError::ReservedPid(0x1FFF))
+        );
+        assert_eq!(
+            try_create_pmt_packet(0x1000, &[(0x10, 27), (0x0F, 15)], 0),
+            Err(PmtError::ReservedPid(0x0F))
+        );
+        assert_eq!(
+            try_create_pmt_packet(0x1000, &[(256, 27), (257, 15)], 0),
+            Ok(create_pmt_packet(0x1000, &[(256, 27), (257, 15)], 0))
+        );
+    }
+
+    #[test]
+    fn es_priority_counted() {
+        let mut collector = EsPriorityCollector::new();
+        collector.push(&af_packet(&[0x20]));
+        collector.push(&af_packet(&[0x60]));
+        collector.push(&af_packet(&[0x40]));
+        collector.push(&Packet::new(Packet::create_packet(
+            false, false, true, 257, 0, 1, 0,
+        )));
+        let counts = collector.counts();
+        assert_eq!(counts.len(), 1);
+        assert_eq!(
+            counts[&256],
+            EsPriorityCounts {
+                priority: 2,
+                likely_keyframes: 1
+            }
+        );
+        assert_eq!(af_packet(&[0x20]).es_priority(), true);
+    }
+
+    #[test]
+    fn hbbtv_descriptors() {
+        // HbbTV application_type 0x0010 on AIT version 3
+        let signalling = Descriptor {
+            tag: 0x6F,
+            data: &[0x80, 0x10, 0xE3],
+        };
+        let apps = signalling.as_application_signalling().unwrap();
+        assert_eq!(apps.applications(), vec![(0x0010, 3)]);
+        assert_eq!(signalling.as_data_broadcast(), None);
+
+        // object carousel id 0x0007 on component 0x0B, 2 byte selector, no text
+        let broadcast = Descriptor {
+            tag: 0x64,
+            data: &[0x00, 0x07, 0x0B, 2, 0xAB, 0xCD, b'e', b'n', b'g', 0],
+        };
+        let data_broadcast = broadcast.as_data_broadcast().unwrap();
+        assert_eq!(data_broadcast.data_broadcast_id, 7);
+        assert_eq!(data_broadcast.component_tag, 0x0B);
+        assert_eq!(data_broadcast.selector, &[0xAB, 0xCD]);
+        assert_eq!(&data_broadcast.language, b"eng");
+        assert_eq!(data_broadcast.text.is_empty(), true);
+        let truncated = Descriptor {
+            tag: 0x64,
+            data: &[0x00, 0x07, 0x0B, 2, 0xAB],
+        };
+        assert_eq!(truncated.as_data_broadcast(), None);
+        assert_eq!(broadcast.as_application_signalling(), None);
+    }
+
+    #[test]
+    fn every_section_in_capture() {
+        let pat = pat_section(0, 0, &[(1, 0x1000)]);
+        // enough program info to push the PMT over two packets
+        let mut program_info = Vec::new();
+        for _ in 0..40 {
+            program_info.extend_from_slice(&[0x05, 4, b'K', b'L', b'V', b'A']);
+        }
+        let streams: [(u16, u8, &[u8]); 1] = [(256, 27, &[])];
+        let pmt_packets =
+            create_pmt_packet_with_descriptors(0x1000, 1, 256, &program_info, &streams, 0);
+        assert_eq!(pmt_packets.len(), 2);
+        let mut capture = Vec::new();
+        capture.extend_from_slice(&psi_packet(0, &pat, 0));
+        capture.extend_from_slice(&Packet::create_packet(false, true, false, 256, 0, 1, 0));
+        for pkt in pmt_packets.iter() {
+            capture.extend_from_slice(pkt);
+        }
+        // a corrupted copy of the PAT is dropped
+        let mut bad_pat = pat.clone();
+        bad_pat[9] ^= 0xFF;
+        capture.extend_from_slice(&psi_packet(0, &bad_pat, 1));
+
+        let found: Vec<(u16, Vec<u8>)> = sections(&capture, &[0, 0x1000]).collect();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0], (0, pat));
+        assert_eq!(found[1].0, 0x1000);
+        let pmt_section: &[u8] = &found[1].1;
+        assert_eq!(pmt_section.len(), 3 + pmt_section.section_length() as usize);
+        let pmt = pmt_section.table_data();
+        assert_eq!(pmt.descriptor_data(), Some(program_info.as_slice()));
+        assert_eq!(sections(&capture, &[0x1100]).count(), 0);
+    }
+
+    #[test]
+    fn sections_interleaved_pids() {
+        let capture = interleaved_pmt_capture();
+        let found: Vec<(u16, Vec<u8>)> = sections(&capture, &[0, 0x1000]).collect();
+        let pids: Vec<u16> = found.iter().map(|(pid, _)| *pid).collect();
+        // the PMT finishes last, after the second PAT
+        assert_eq!(pids, vec![0, 0, 0x1000]);
+        let pmt_section: &[u8] = &found[2].1;
+        assert_eq!(pmt_section.len(), 3 + pmt_section.section_length() as usize);
+        assert_eq!(calc_crc32(pmt_section), pmt_section.crc32());
+        assert_eq!(pmt_section.table_data().pcr_pid(), 256);
+    }
+
+    #[test]
+    fn strip_nulls() {
+        let null = Packet::create_packet(false, false, false, 0x1FFF, 0, 1, 0);
+        let data = |cc| Packet::create_packet(false, false, false, 256, 0, 1, cc);
+        let stream = [null, data(0), null, null, null, data(1), data(2), null];
+        let mut stripper = NullStripper::new();
+        let mut passed = Vec::new();
+        let mut gaps = Vec::new();
+        for pkt in stream.iter() {
+            if let Some(kept) = stripper.strip(Packet::new(*pkt)) {
+                passed.push(kept);
+                gaps.push(stripper.removed_before_last());
+            }
+        }
+        assert_eq!(passed, vec![data(0), data(1), data(2)]);
+        assert_eq!(gaps, vec![1, 3, 0]);
+        assert_eq!(stripper.removed_count(), 5);
+    }
+
+    #[test]
+    fn pat_network_entry() {
+        let section = pat_section(0, 0, &[(0, 0x0010), (1, 0x1000), (2, 0x1100)]);
+        let section: &[u8] = &section;
+        let pat = section.table_data();
+        assert_eq!(pat.network_pid(), Some(0x0010));
+        assert_eq!(
+            pat.programs().collect::<Vec<PatEntry>>(),
+            vec![
+                PatEntry::Network(0x0010),
+                PatEntry::Program(1, 0x1000),
+                PatEntry::Program(2, 0x1100)
+            ]
+        );
+        let section = pat_section(0, 0, &[(1, 0x1000)]);
+        let section: &[u8] = &section;
+        assert_eq!(section.table_data().network_pid(), None);
+    }
+
+    #[test]
+    fn repack_shrunk_pmt() {
+        let pkt = Packet::new(create_pmt_packet(0x1000, &[(256, 27), (257, 15)], 4));
+        let tables = pkt.tables().unwrap();
+        let mut editor = PmtEditor::new(tables.section_data()).unwrap();
+        let rebuilt = editor.remove_stream(257).build(0x1000, 5).unwrap();
+        assert_eq!(rebuilt.len(), 188);
+        assert_eq!(rebuilt, create_pmt_packet(0x1000, &[(256, 27)], 5));
+
+        let rebuilt = Packet::new(rebuilt);
+        assert_eq!(rebuilt.pid(), 0x1000);
+        assert_eq!(rebuilt.cc(), 5);
+        let tables = rebuilt.tables().unwrap();
+        // between the pointer field and the section is all filler
+        assert_eq!(
+            rebuilt.data()[5..5 + rebuilt.data()[4] as usize]
+                .iter()
+                .all(|b| *b == 0xFF),
+            true
+        );
+        let section = tables.section_data();
+        assert_eq!(calc_crc32(section), section.crc32());
+        assert_eq!(verify_section_length(section), true);
+        let pmt = section.table_data();
+        let es = pmt.elementary_streams();
+        assert_eq!(es.stream_pid(), 256);
+        assert_eq!(es.next_stream(), None);
+    }
+
+    #[test]
+    fn count_programs() {
+        assert_eq!(program_count(&create_two_program_stream()), Some(2));
+        let with_network = pat_section(0, 0, &[(0, 0x0010), (1, 0x1000)]);
+        assert_eq!(program_count(&psi_packet(0, &with_network, 0)), Some(1));
+        assert_eq!(program_count(&hex_to_bin(SAMPLE_PMT_HEX)), None);
+    }
+
+    #[test]
+    fn repeated_pat_validated_once() {
+        let pat = pat_section(0, 0, &[(1, 0x1000)]);
+        let mut validator = CachedValidator::new();
+        for _ in 0..100 {
+            assert_eq!(validator.validate(0, &pat), true);
+        }
+        assert_eq!(validator.crc_computations(), 1);
+
+        // a changed section is checked afresh, and a corrupt one caught
+        let mut corrupt = pat.clone();
+        corrupt[9] ^= 0xFF;
+        assert_eq!(validator.validate(0, &corrupt), false);
+        assert_eq!(validator.validate(0, &corrupt), false);
+        assert_eq!(validator.crc_computations(), 2);
+        assert_eq!(validator.validate(0, &pat), true);
+        assert_eq!(validator.crc_computations(), 3);
+    }
+
+    #[test]
+    fn borrowed_packet_view() {
+        let mut buf = sample_capture();
+        // PCR, private data and an extension then stuffing
+        let mut af = vec![0x13];
+        af.extend_from_slice(&pcr_bytes(0x800, 0));
+        af.extend_from_slice(&[2, 0xAB, 0xCD, 1, 0x00, 0xFF, 0xFF]);
+        buf.extend_from_slice(af_packet(&af).data());
+        for window in buf.chunks_exact(188) {
+            let owned = Packet::new(window.try_into().unwrap());
+            let borrowed = PacketRef::new(window).unwrap();
+            assert_eq!(borrowed.data(), &owned.data()[..]);
+            assert_eq!(borrowed.fields(), owned.fields());
+            assert_eq!(borrowed.has_pcr(), owned.has_pcr());
+            assert_eq!(borrowed.pcr(), owned.pcr());
+            assert_eq!(
+                borrowed.transport_private_data(),
+                owned.transport_private_data()
+            );
+            assert_eq!(borrowed.extension(), owned.extension());
+            assert_eq!(borrowed.stuffing(), owned.stuffing());
+            assert_eq!(borrowed.payload_data(), owned.payload_data());
+            if owned.pid() == 0 || owned.pid() == 0x1000 {
+                assert_eq!(borrowed.tables(), owned.tables());
+            }
+            assert_eq!(borrowed.to_packet().data(), owned.data());
+        }
+        let last = PacketRef::new(&buf[188 * 3..]).unwrap();
+        assert_eq!(last.pcr(), 0x800 * 300);
+        assert_eq!(last.transport_private_data(), &[0xAB, 0xCD]);
+        assert_eq!(PacketRef::new(&buf[..187]), None);
+        assert_eq!(PacketRef::new(&buf[1..]), None);
+    }
+
+    #[test]
+    fn tables_out_of_range_pointer() {
+        // pointer_field 183 skips the rest of a full payload, 255 points past it
+        for pointer in [183, 255].iter() {
+            let mut data = Packet::create_packet(false, true, false, 0, 0, 1, 0);
+            data[4] = *pointer;
+            let pkt = Packet::new(data);
+            assert_eq!(pkt.tables(), None);
+            assert_eq!(PacketRef::new(&data).unwrap().tables(), None);
+        }
+
+        // an adaptation field taking up the whole packet leaves no pointer_field
+        let af = stuffing_only_af(183);
+        let mut data = Packet::create_packet(false, true, false, 0, 0, 3, 0);
+        data[4..].copy_from_slice(&af);
+        let pkt = Packet::new(data);
+        assert_eq!(pkt.payload_data().len(), 0);
+        assert_eq!(pkt.tables(), None);
+        assert_eq!(PacketRef::new(&data).unwrap().tables(), None);
+    }
+
+    #[test]
+    fn packet_iter_truncated() {
+        let mut buf = sample_capture();
+        let whole = buf.len() / 188;
+        buf.extend_from_slice(&[0x47, 0x00, 0x11]);
+        let mut iter = Packet::iter(&buf);
+        assert_eq!(iter.size_hint(), (whole, Some(whole)));
+        let pids: Vec<u16> = iter.by_ref().map(|p| p.pid()).collect();
+        assert_eq!(pids.len(), whole);
+        assert_eq!(pids[0], 0);
+        assert_eq!(pids[1], 0x1000);
+        assert_eq!(iter.remainder(), 3);
+        assert_eq!(iter.next(), None);
+        // shorter than one packet yields nothing
+        let mut short = Packet::iter(&buf[..100]);
+        assert_eq!(short.next(), None);
+        assert_eq!(short.remainder(), 100);
+        assert_eq!(Packet::iter(&[]).remainder(), 0);
+    }
+
+    #[test]
+    fn synth_pat_round_trip() {
+        assert_roundtrip_pat(&[123, 456]);
+    }
+
+    #[test]
+    fn synth_pmt_round_trip() {
+        assert_roundtrip_pmt(0x1000, &[(256, 27)]);
+    }
+
     #[test]
     fn synth_pat() {
         let raw_pkt = create_pat_packet(&[123, 456], 9);
@@ -1,4 +1,9 @@
+use crate::continuity::NULL_PID;
 use byteorder::{BigEndian, ByteOrder};
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::{Infallible, TryInto};
+use std::fmt;
+use std::io::{self, Read, Write};
 
 pub struct Packet {
     data: PacketData,
@@ -7,19 +12,163 @@ pub struct Packet {
 
 impl Packet {
     pub fn new(data: PacketData) -> Packet {
-        let header = BigEndian::read_u32(&data[0..4]);
+        let header = parse_header(&data);
         return Packet {
             data: data,
             header: header,
         };
     }
 
+    pub fn data(&self) -> &PacketData {
+        return &self.data;
+    }
+
+    // a 192 byte M2TS packet is a 4 byte TP_extra_header then the 188 byte packet. The top
+    // 2 bits of the header are copy_permission_indicator and the low 30 bits the 27MHz
+    // arrival_time_stamp, which comes back alone with the copy control bits dropped
+    pub fn from_m2ts(data: &[u8; 192]) -> (u32, Packet) {
+        let timestamp = BigEndian::read_u32(&data[0..4]) & 0x3FFF_FFFF;
+        return (timestamp, Packet::new(data[4..].try_into().unwrap()));
+    }
+
+    // a 204 byte packet is the 188 byte packet followed by 16 bytes of Reed-Solomon parity,
+    // which is dropped here without checking
+    pub fn from_204(data: &[u8; 204]) -> Packet {
+        return Packet::new(data[..188].try_into().unwrap());
+    }
+
+    // the 16 parity bytes of a 204 byte packet, for running RS(204,188) correction over
+    pub fn parity_bytes(data: &[u8; 204]) -> &[u8] {
+        return &data[188..];
+    }
+
+    // the M2TS form of this packet with timestamp as its arrival_time_stamp, anything past
+    // 30 bits is dropped and copy_permission_indicator left as 0
+    pub fn to_m2ts(&self, timestamp: u32) -> [u8; 192] {
+        let mut m2ts = [0u8; 192];
+        BigEndian::write_u32(&mut m2ts[0..4], timestamp & 0x3FFF_FFFF);
+        m2ts[4..].copy_from_slice(&self.data);
+        return m2ts;
+    }
+
+    // PUSI on a packet with a payload, for a PSI PID this means a section starts here
+    pub fn is_section_start(&self) -> bool {
+        return self.has_payload() && self.pusi();
+    }
+
+    // index into the 188 bytes where the payload starts, None when afc says there is no
+    // payload or the adaptation field leaves no room for one
+    pub fn payload_offset(&self) -> Option<usize> {
+        if !self.has_payload() {
+            return None;
+        }
+        let mut offset = 4;
+        if self.has_adaptation_field() {
+            offset += 1 + self.aflen() as usize;
+        }
+        if offset >= 188 {
+            return None;
+        }
+        return Some(offset);
+    }
+
+    // bytes of payload after the header and any adaptation field, 0 when there is none
+    pub fn payload_len(&self) -> usize {
+        return self.payload_offset().map_or(0, |offset| 188 - offset);
+    }
+
+    // share of the 184 bytes after the header that are payload rather than adaptation
+    // field or stuffing
+    pub fn payload_efficiency(&self) -> f32 {
+        return self.payload_len() as f32 / FULL_PAYLOAD_LEN as f32;
+    }
+
+    // the PID, or None when the sync byte says this window isn't really a packet
+    pub fn pid_checked(&self) -> Option<u16> {
+        if self.sync() != 0x47 {
+            return None;
+        }
+        return Some(self.pid());
+    }
+
     pub fn with_cc(data: PacketData, cc: u8) -> PacketData {
         let mut updated = data;
         updated[3] = (data[3] & 0xF0) + (cc & 0xF);
         return updated;
     }
 
+    // rewrites the PCR of a packet already carrying one, others are returned unchanged
+    pub fn with_pcr(data: PacketData, pcr: u64) -> PacketData {
+        let mut updated = data;
+        if Packet::new(data).has_pcr() {
+            let base = (pcr / 300) & 0x1_FFFF_FFFF;
+            let ext = pcr % 300;
+            BigEndian::write_u32(&mut updated[6..10], (base >> 1) as u32);
+            updated[10] = (((base & 0x1) << 7) as u8) | 0x7E | ((ext >> 8) as u8 & 0x1);
+            updated[11] = ext as u8;
+        }
+        return updated;
+    }
+
+    // a null packet some muxers put down to hold the place of a PCR filled in on a second
+    // pass: null PID with an adaptation field flagging a PCR and long enough to hold one
+    pub fn is_reserved_null_for_pcr(&self) -> bool {
+        return self.pid() == NULL_PID && self.has_pcr() && self.aflen() >= 7;
+    }
+
+    // turns a reserved null into an adaptation-field-only PCR carrier on pid, keeping its cc
+    // (which the caller can fix up with with_cc). Anything else is returned unchanged
+    pub fn reclaim_null_for_pcr(
+        data: PacketData,
+        pid: u16,
+        pcr_base: u64,
+        pcr_ext: u16,
+    ) -> PacketData {
+        let reserved = Packet::new(data);
+        if !reserved.is_reserved_null_for_pcr() {
+            return data;
+        }
+        let header = HeaderFields {
+            tei: false,
+            pusi: false,
+            priority: false,
+            pid: pid,
+            tsc: 0,
+            afc: 2,
+            cc: reserved.cc(),
+        };
+        let af = [0x10, 0, 0, 0, 0, 0x7E, 0];
+        let carrier = match Packet::create_packet_full(header, Some(&af), &[]) {
+            Ok(carrier) => carrier,
+            Err(_) => return data,
+        };
+        let pcr = (pcr_base & 0x1_FFFF_FFFF) * 300 + (pcr_ext as u64 % 300);
+        return Packet::with_pcr(carrier, pcr);
+    }
+
+    // marks a descrambled packet as clear by zeroing tsc, afc and cc are left alone
+    pub fn clear_scrambling(data: PacketData) -> PacketData {
+        let mut updated = data;
+        updated[3] = data[3] & 0x3F;
+        return updated;
+    }
+
+    // marks which key a (re-)scrambled packet uses, afc and cc are left alone
+    pub fn set_scrambling(data: PacketData, sc: ScramblingControl) -> PacketData {
+        let mut updated = data;
+        updated[3] = (data[3] & 0x3F) | (sc.bits() << 6);
+        return updated;
+    }
+
+    pub fn scrambling(&self) -> ScramblingControl {
+        return ScramblingControl::from_bits(self.tsc());
+    }
+
+    // afc has to agree with the layout the payload needs: a full 184 byte payload leaves
+    // no room for an adaptation field so afc must be 1, anything shorter gets padded out
+    // with a stuffing adaptation field so afc must be 3, and an empty payload leaves only
+    // the adaptation field so afc must be 2. Any other afc is rejected rather than
+    // silently rewritten.
     pub fn create_packet_with_payload(
         tei: bool,
         pusi: bool,
@@ -29,35 +178,121 @@ impl Packet {
         afc: u8,
         cc: u8,
         payload: &[u8],
-    ) -> PacketData {
-        const FULL_PAYLOAD_LEN: usize = 184;
+    ) -> Result<PacketData, PacketError> {
         let mut payload_len = payload.len();
-        let mut offset = 4;
-        let mut data: PacketData;
         if payload_len > FULL_PAYLOAD_LEN {
             // yep! silently dropping any extra payload passed in
             // TODO: return two packets with the payload split across
             payload_len = FULL_PAYLOAD_LEN;
-        } 
-
-        if payload_len == FULL_PAYLOAD_LEN {
-            data = Packet::create_packet(tei, pusi, priority, pid, tsc, afc, cc);
-        } else {
-            if payload_len > FULL_PAYLOAD_LEN - 2 {
-                // again dropping data if we can't stick it in after adding the adaptation field
-                // TODO: return two packets with the payload split across
-                payload_len = FULL_PAYLOAD_LEN - 2;
+        }
+
+        let (required_afc, af) = stuffing_af_for(payload_len);
+        if afc != required_afc {
+            return Err(PacketError::AfcMismatch {
+                requested: afc,
+                required: required_afc,
+            });
+        }
+
+        let mut data = Packet::create_packet(tei, pusi, priority, pid, tsc, afc, cc);
+        let offset = 4 + af.len();
+        data[4..offset].copy_from_slice(&af);
+        data[offset..offset + payload_len].copy_from_slice(&payload[..payload_len]);
+        return Ok(data);
+    }
+
+    // lays out the header, the adaptation field body (flags onwards) given with its length
+    // byte prefixed, then the payload. Short packets get 0xFF stuffing at the end of the
+    // adaptation field, adding a stuffing-only one when none was given. header.afc has to
+    // match the resulting layout
+    pub fn create_packet_full(
+        header: HeaderFields,
+        af: Option<&[u8]>,
+        payload: &[u8],
+    ) -> Result<PacketData, PacketError> {
+        let af_len = af.map_or(0, |af| 1 + af.len());
+        let total = af_len + payload.len();
+        if total > FULL_PAYLOAD_LEN {
+            return Err(PacketError::TooLarge(total));
+        }
+        let mut af_bytes = match af {
+            Some(af) => {
+                let mut af_bytes = vec![0];
+                af_bytes.extend_from_slice(af);
+                af_bytes
+            }
+            None => Vec::new(),
+        };
+        if total < FULL_PAYLOAD_LEN {
+            if af_bytes.is_empty() {
+                af_bytes = stuffing_af_for(payload.len()).1;
+            } else {
+                if af_bytes.len() == 1 {
+                    af_bytes.push(0); // no adaptation field flags set
+                }
+                af_bytes.resize(FULL_PAYLOAD_LEN - payload.len(), 0xFF);
             }
-            data = Packet::create_packet(tei, pusi, priority, pid, tsc, afc | 0x2, cc);
-            let aflen = FULL_PAYLOAD_LEN - 1 - payload_len;
-            data[4] = aflen as u8;
-            data[5] = 0; // no additional adaptation field flags set
-            offset = 4 + 1 + aflen;
         }
-        for pos in 0..payload_len {
-            data[offset + pos] = payload[pos];
+        if !af_bytes.is_empty() {
+            af_bytes[0] = (af_bytes.len() - 1) as u8;
+        }
+
+        let mut required_afc = 0;
+        if !af_bytes.is_empty() {
+            required_afc |= 0x2;
+        }
+        if !payload.is_empty() {
+            required_afc |= 0x1;
+        }
+        if header.afc != required_afc {
+            return Err(PacketError::AfcMismatch {
+                requested: header.afc,
+                required: required_afc,
+            });
+        }
+
+        let mut data = Packet::create_packet(
+            header.tei,
+            header.pusi,
+            header.priority,
+            header.pid,
+            header.tsc,
+            header.afc,
+            header.cc,
+        );
+        let offset = 4 + af_bytes.len();
+        data[4..offset].copy_from_slice(&af_bytes);
+        data[offset..offset + payload.len()].copy_from_slice(payload);
+        return Ok(data);
+    }
+
+    // create_packet, refusing values that would otherwise be silently truncated to fit
+    pub fn try_create_packet(
+        tei: bool,
+        pusi: bool,
+        priority: bool,
+        pid: u16,
+        tsc: u8,
+        afc: u8,
+        cc: u8,
+    ) -> Result<PacketData, PacketError> {
+        let limits = [
+            ("pid", pid, 0x1FFF),
+            ("tsc", tsc as u16, 3),
+            ("afc", afc as u16, 3),
+            ("cc", cc as u16, 0xF),
+        ];
+        for (field, value, max) in limits.iter() {
+            if value > max {
+                return Err(PacketError::OutOfRange {
+                    field: field,
+                    value: *value,
+                });
+            }
         }
-        return data;
+        return Ok(Packet::create_packet(
+            tei, pusi, priority, pid, tsc, afc, cc,
+        ));
     }
 
     pub fn create_packet(
@@ -91,6 +326,89 @@ impl Packet {
 
 pub type PacketData = [u8; 188];
 
+const FULL_PAYLOAD_LEN: usize = 184;
+
+// an adaptation field that is there purely to pad the packet out: the length byte, a flags
+// byte with nothing set then 0xFF stuffing, len being the adaptation_field_length
+pub fn stuffing_only_af(len: u8) -> Vec<u8> {
+    let mut af = vec![0xFF; 1 + len as usize];
+    af[0] = len;
+    if len > 0 {
+        af[1] = 0;
+    }
+    return af;
+}
+
+// returns the afc and adaptation field bytes (length byte, zeroed flags then 0xFF
+// stuffing) needed so that header + adaptation field + payload fills out all 188 bytes
+pub fn stuffing_af_for(payload_len: usize) -> (u8, Vec<u8>) {
+    if payload_len >= FULL_PAYLOAD_LEN {
+        return (1, Vec::new());
+    }
+    // one byte goes to the length field itself, a zero length field is a single stuffing byte
+    let af = stuffing_only_af((FULL_PAYLOAD_LEN - 1 - payload_len) as u8);
+    if payload_len == 0 {
+        return (2, af);
+    }
+    return (3, af);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeaderFields {
+    pub tei: bool,
+    pub pusi: bool,
+    pub priority: bool,
+    pub pid: u16,
+    pub tsc: u8,
+    pub afc: u8,
+    pub cc: u8,
+}
+
+// transport_scrambling_control as DVB uses it, the key parity for scrambled packets
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScramblingControl {
+    Clear,
+    Reserved,
+    EvenKey,
+    OddKey,
+}
+
+impl ScramblingControl {
+    pub fn from_bits(tsc: u8) -> ScramblingControl {
+        return match tsc & 0x3 {
+            0 => ScramblingControl::Clear,
+            1 => ScramblingControl::Reserved,
+            2 => ScramblingControl::EvenKey,
+            _ => ScramblingControl::OddKey,
+        };
+    }
+
+    pub fn bits(&self) -> u8 {
+        return match self {
+            ScramblingControl::Clear => 0,
+            ScramblingControl::Reserved => 1,
+            ScramblingControl::EvenKey => 2,
+            ScramblingControl::OddKey => 3,
+        };
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PacketError {
+    // the afc passed in contradicts whether the payload needs an adaptation field
+    AfcMismatch { requested: u8, required: u8 },
+    // adaptation field plus payload came to more than the 184 bytes after the header
+    TooLarge(usize),
+    // a header field given more bits than it has, e.g. a PID over 0x1FFF
+    OutOfRange { field: &'static str, value: u16 },
+}
+
+// reads only the 4 header bytes so pid()/pusi()/cc() etc from the u32 PacketHeader impl
+// can be used to filter without building a Packet for every 188 bytes
+pub fn parse_header(bytes: &[u8]) -> u32 {
+    return BigEndian::read_u32(&bytes[0..4]);
+}
+
 pub trait PacketHeader {
     fn sync(&self) -> u8;
     fn tei(&self) -> bool;
@@ -102,6 +420,7 @@ pub trait PacketHeader {
     fn has_adaptation_field(&self) -> bool;
     fn has_payload(&self) -> bool;
     fn cc(&self) -> u8;
+    fn fields(&self) -> HeaderFields;
 }
 
 impl PacketHeader for Packet {
@@ -135,6 +454,9 @@ impl PacketHeader for Packet {
     fn cc(&self) -> u8 {
         return self.header.cc();
     }
+    fn fields(&self) -> HeaderFields {
+        return self.header.fields();
+    }
 }
 
 impl PacketHeader for u32 {
@@ -168,13 +490,30 @@ impl PacketHeader for u32 {
     fn cc(&self) -> u8 {
         return (self & 0xf) as u8;
     }
+    fn fields(&self) -> HeaderFields {
+        return HeaderFields {
+            tei: self.tei(),
+            pusi: self.pusi(),
+            priority: self.priority(),
+            pid: self.pid(),
+            tsc: self.tsc(),
+            afc: self.afc(),
+            cc: self.cc(),
+        };
+    }
 }
 
 pub trait AdaptationField {
     fn aflen(&self) -> u8;
     fn is_discontinuity(&self) -> bool;
     fn is_random_access(&self) -> bool;
-    fn priority_stream(&self) -> bool;
+    fn es_priority(&self) -> bool;
+    #[deprecated(
+        note = "renamed es_priority to tell it apart from the header's transport_priority"
+    )]
+    fn priority_stream(&self) -> bool {
+        return self.es_priority();
+    }
     fn has_pcr(&self) -> bool;
     fn has_opcr(&self) -> bool;
     fn has_splice_countdown(&self) -> bool;
@@ -192,56 +531,197 @@ pub trait AdaptationField {
 }
 
 fn read_pcr_data(buf: &[u8]) -> u64 {
+    // 33 bit base at 90kHz, 6 reserved bits then a 9 bit extension at 27MHz
     let high_int = BigEndian::read_u32(&buf[0..4]) as u64;
-    let low_short = BigEndian::read_u16(&buf[4..6]) as u64;
-    let upper = high_int << 1 + (low_short & 0x8000 >> 15);
-    let lower = low_short & 0x1ff;
-    /* println!(
-        "\nhexpcr: {:X}{:X}{:X}{:X}{:X}{:X} high_int: {:X}, upper: {:X} lower: {:X}",
-        buf[0], buf[1], buf[2], buf[3], buf[4], buf[5], high_int,upper, lower
-    );
-    */
+    let upper = (high_int << 1) | (buf[4] as u64 >> 7);
+    let lower = ((buf[4] as u64 & 0x1) << 8) | buf[5] as u64;
     return (upper * 300) + lower;
 }
 
-fn pcr_to_nanos(pcr: u64) -> u64 {
-    return (pcr * 1_000_000_000) / 27_000_000;
+// 27MHz ticks to nanoseconds, as pcr_nanos and opcr_nanos give them
+pub fn pcr_to_nanos(pcr: u64) -> u64 {
+    return (pcr * 1_000) / 27;
 }
 
-impl AdaptationField for Packet {
+// a packet read in place out of a larger buffer, for when copying each 188 bytes into a
+// Packet costs more than the fields being looked at. Accessors behave exactly as Packet's
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PacketRef<'a>(&'a [u8]);
+
+impl<'a> PacketRef<'a> {
+    // None unless data holds at least 188 bytes starting on the sync byte, anything past
+    // the first 188 is ignored
+    pub fn new(data: &'a [u8]) -> Option<PacketRef<'a>> {
+        if data.len() < 188 || data[0] != 0x47 {
+            return None;
+        }
+        return Some(PacketRef(&data[..188]));
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        return self.0;
+    }
+
+    // an owned copy, for keeping the packet past the buffer it was read from
+    pub fn to_packet(&self) -> Packet {
+        return Packet::new(self.0.try_into().unwrap());
+    }
+
+    // flags byte of the adaptation field, zero whenever there is no room for one
+    fn af_flags(&self) -> u8 {
+        if self.has_adaptation_field() && self.aflen() > 0 {
+            return self.0[5];
+        }
+        return 0;
+    }
+
+    // one past the last adaptation field byte
+    fn af_end(&self) -> usize {
+        return (5 + self.aflen() as usize).min(188);
+    }
+
+    // the optional fields come in flag order (pcr, opcr, splice countdown, private data,
+    // extension) each only present when flagged, so the offset of any one of them is the
+    // sum of the sizes of the flagged fields ahead of it
+    fn af_field_offset(&self, flag: u8) -> usize {
+        let flags = self.af_flags();
+        let mut offset = 6;
+        if flag < 0x10 && 0 != flags & 0x10 {
+            offset += 6;
+        }
+        if flag < 0x8 && 0 != flags & 0x8 {
+            offset += 6;
+        }
+        if flag < 0x4 && 0 != flags & 0x4 {
+            offset += 1;
+        }
+        if flag < 0x2 && 0 != flags & 0x2 && offset < 188 {
+            offset += 1 + self.0[offset] as usize;
+        }
+        return offset.min(188);
+    }
+
+    // a length prefixed field inside the adaptation field, clamped to the field's end
+    fn af_length_prefixed(&self, offset: usize) -> &'a [u8] {
+        let af_end = self.af_end();
+        if offset >= af_end {
+            return &[];
+        }
+        let start = offset + 1;
+        let end = (start + self.0[offset] as usize).min(af_end);
+        return &self.0[start.min(end)..end];
+    }
+
+    // the slice returning accessors, borrowing from the buffer rather than from self so
+    // Packet can hand them out through a PacketRef made on the spot
+    fn af_private_data(&self) -> &'a [u8] {
+        if self.has_transport_private_data() {
+            return self.af_length_prefixed(self.af_field_offset(0x2));
+        }
+        return &[];
+    }
+
+    fn af_extension(&self) -> &'a [u8] {
+        if self.has_extension() {
+            return self.af_length_prefixed(self.af_field_offset(0x1));
+        }
+        return &[];
+    }
+
+    fn af_stuffing(&self) -> &'a [u8] {
+        if !self.has_adaptation_field() || self.aflen() == 0 {
+            return &[];
+        }
+        let af_end = self.af_end();
+        let mut start = self.af_field_offset(0x1);
+        if self.has_extension() && start < af_end {
+            start += 1 + self.0[start] as usize;
+        }
+        return &self.0[start.min(af_end)..af_end];
+    }
+
+    fn payload(&self) -> &'a [u8] {
+        let mut offset = 4;
+        if self.has_adaptation_field() {
+            // skip the adaptation_field_length byte as well as the field itself
+            offset += 1 + self.aflen() as usize;
+        }
+        return &self.0[offset.min(188)..188];
+    }
+}
+
+impl<'a> PacketHeader for PacketRef<'a> {
+    fn sync(&self) -> u8 {
+        return parse_header(self.0).sync();
+    }
+    fn tei(&self) -> bool {
+        return parse_header(self.0).tei();
+    }
+    fn pusi(&self) -> bool {
+        return parse_header(self.0).pusi();
+    }
+    fn priority(&self) -> bool {
+        return parse_header(self.0).priority();
+    }
+    fn pid(&self) -> u16 {
+        return parse_header(self.0).pid();
+    }
+    fn tsc(&self) -> u8 {
+        return parse_header(self.0).tsc();
+    }
+    fn afc(&self) -> u8 {
+        return parse_header(self.0).afc();
+    }
+    fn has_adaptation_field(&self) -> bool {
+        return parse_header(self.0).has_adaptation_field();
+    }
+    fn has_payload(&self) -> bool {
+        return parse_header(self.0).has_payload();
+    }
+    fn cc(&self) -> u8 {
+        return parse_header(self.0).cc();
+    }
+    fn fields(&self) -> HeaderFields {
+        return parse_header(self.0).fields();
+    }
+}
+
+impl<'a> AdaptationField for PacketRef<'a> {
     fn aflen(&self) -> u8 {
-        return self.data[4];
+        return self.0[4];
     }
     fn is_discontinuity(&self) -> bool {
-        return 0 != self.data[5] & 0x80;
+        return 0 != self.af_flags() & 0x80;
     }
     fn is_random_access(&self) -> bool {
-        return 0 != self.data[5] & 0x40;
+        return 0 != self.af_flags() & 0x40;
     }
-    fn priority_stream(&self) -> bool {
-        return 0 != self.data[5] & 0x20;
+    // elementary_stream_priority_indicator
+    fn es_priority(&self) -> bool {
+        return 0 != self.af_flags() & 0x20;
     }
     fn has_pcr(&self) -> bool {
-        return 0 != self.data[5] & 0x10;
+        return 0 != self.af_flags() & 0x10;
     }
     fn has_opcr(&self) -> bool {
-        return 0 != self.data[5] & 0x8;
+        return 0 != self.af_flags() & 0x8;
     }
     fn has_splice_countdown(&self) -> bool {
-        return 0 != self.data[5] & 0x4;
+        return 0 != self.af_flags() & 0x4;
     }
 
     fn has_transport_private_data(&self) -> bool {
-        return 0 != self.data[5] & 0x2;
+        return 0 != self.af_flags() & 0x2;
     }
 
     fn has_extension(&self) -> bool {
-        return 0 != self.data[5] & 0x1;
+        return 0 != self.af_flags() & 0x1;
     }
 
     fn pcr(&self) -> u64 {
         if self.has_pcr() {
-            return read_pcr_data(&self.data[6..13]);
+            let offset = self.af_field_offset(0x10);
+            return read_pcr_data(&self.0[offset..offset + 6]);
         }
         return 0;
     }
@@ -249,12 +729,13 @@ impl AdaptationField for Packet {
     fn pcr_nanos(&self) -> u64 {
         return pcr_to_nanos(self.pcr());
     }
+
     fn opcr(&self) -> u64 {
-        if self.has_pcr() {
-            return read_pcr_data(&self.data[13..20]);
-        } else {
-            return self.pcr();
+        if self.has_opcr() {
+            let offset = self.af_field_offset(0x8);
+            return read_pcr_data(&self.0[offset..offset + 6]);
         }
+        return 0;
     }
 
     fn opcr_nanos(&self) -> u64 {
@@ -263,104 +744,92 @@ impl AdaptationField for Packet {
 
     fn splice_countdown(&self) -> u8 {
         if self.has_splice_countdown() {
-            if self.has_pcr() {
-                if self.has_opcr() {
-                    return self.data[20];
-                } else {
-                    return self.data[13];
-                }
-            } else {
-                if self.has_opcr() {
-                    return self.data[13];
-                } else {
-                    return self.data[6];
-                }
-            }
-        } else {
-            return 0;
+            return self.0[self.af_field_offset(0x4)];
         }
+        return 0;
     }
 
     fn transport_private_data_len(&self) -> u8 {
         if self.has_transport_private_data() {
-            if self.has_splice_countdown() {
-                if self.has_pcr() {
-                    if self.has_opcr() {
-                        return self.data[21];
-                    } else {
-                        return self.data[14];
-                    }
-                } else {
-                    if self.has_opcr() {
-                        return self.data[14];
-                    } else {
-                        return self.data[7];
-                    }
-                }
-            } else {
-                if self.has_pcr() {
-                    if self.has_opcr() {
-                        return self.data[20];
-                    } else {
-                        return self.data[14];
-                    }
-                } else {
-                    if self.has_opcr() {
-                        return self.data[14];
-                    } else {
-                        return self.data[7];
-                    }
-                }
-            }
-        } else {
-            return 0;
+            return self.0[self.af_field_offset(0x2)];
         }
+        return 0;
     }
 
     fn transport_private_data(&self) -> &[u8] {
-        if self.has_transport_private_data() {
-            let trans_len = self.transport_private_data_len() as usize;
-            if self.has_splice_countdown() {
-                if self.has_pcr() {
-                    if self.has_opcr() {
-                        return &self.data[22..22 + trans_len];
-                    } else {
-                        return &self.data[15..15 + trans_len];
-                    }
-                } else {
-                    if self.has_opcr() {
-                        return &self.data[15..15 + trans_len];
-                    } else {
-                        return &self.data[8..8 + trans_len];
-                    }
-                }
-            } else {
-                if self.has_pcr() {
-                    if self.has_opcr() {
-                        return &self.data[21..21 + trans_len];
-                    } else {
-                        return &self.data[15..15 + trans_len];
-                    }
-                } else {
-                    if self.has_opcr() {
-                        return &self.data[15..15 + trans_len];
-                    } else {
-                        return &self.data[8..8 + trans_len];
-                    }
-                }
-            }
-        } else {
-            return &[];
-        }
+        return self.af_private_data();
+    }
+    // the adaptation field extension after its length byte, starting with its flags
+    fn extension(&self) -> &[u8] {
+        return self.af_extension();
     }
+    // whatever is left of the adaptation field after the last flagged field
+    fn stuffing(&self) -> &[u8] {
+        return self.af_stuffing();
+    }
+}
 
+impl Packet {
+    // a borrowed view of this packet, no sync byte check
+    pub fn as_packet_ref(&self) -> PacketRef<'_> {
+        return PacketRef(&self.data);
+    }
+}
+
+impl AdaptationField for Packet {
+    fn aflen(&self) -> u8 {
+        return self.as_packet_ref().aflen();
+    }
+    fn is_discontinuity(&self) -> bool {
+        return self.as_packet_ref().is_discontinuity();
+    }
+    fn is_random_access(&self) -> bool {
+        return self.as_packet_ref().is_random_access();
+    }
+    fn es_priority(&self) -> bool {
+        return self.as_packet_ref().es_priority();
+    }
+    fn has_pcr(&self) -> bool {
+        return self.as_packet_ref().has_pcr();
+    }
+    fn has_opcr(&self) -> bool {
+        return self.as_packet_ref().has_opcr();
+    }
+    fn has_splice_countdown(&self) -> bool {
+        return self.as_packet_ref().has_splice_countdown();
+    }
+    fn has_transport_private_data(&self) -> bool {
+        return self.as_packet_ref().has_transport_private_data();
+    }
+    fn has_extension(&self) -> bool {
+        return self.as_packet_ref().has_extension();
+    }
+    fn pcr(&self) -> u64 {
+        return self.as_packet_ref().pcr();
+    }
+    fn pcr_nanos(&self) -> u64 {
+        return self.as_packet_ref().pcr_nanos();
+    }
+    fn opcr(&self) -> u64 {
+        return self.as_packet_ref().opcr();
+    }
+    fn opcr_nanos(&self) -> u64 {
+        return self.as_packet_ref().opcr_nanos();
+    }
+    fn splice_countdown(&self) -> u8 {
+        return self.as_packet_ref().splice_countdown();
+    }
+    fn transport_private_data_len(&self) -> u8 {
+        return self.as_packet_ref().transport_private_data_len();
+    }
+    fn transport_private_data(&self) -> &[u8] {
+        return self.as_packet_ref().af_private_data();
+    }
     fn extension(&self) -> &[u8] {
-        // TODO: implement this once I need the data in it
-        return &[];
+        return self.as_packet_ref().af_extension();
     }
     fn stuffing(&self) -> &[u8] {
-        // TODO: maybe let folks grab the stuffing someday, though not sure what the purpose would be
-        return &[];
+        return self.as_packet_ref().af_stuffing();
     }
 }
 
@@ -368,12 +837,611 @@ pub trait Payload {
     fn payload_data(&self) -> &[u8];
 }
 
+impl<'a> Payload for PacketRef<'a> {
+    fn payload_data(&self) -> &[u8] {
+        return self.payload();
+    }
+}
+
 impl Payload for Packet {
     fn payload_data(&self) -> &[u8] {
-        let mut offset = 4;
-        if self.has_adaptation_field() {
-            offset += self.aflen() as usize;
+        return self.as_packet_ref().payload();
+    }
+}
+
+// walks a buffer 188 bytes at a time handing out PacketRefs, the sync byte isn't checked so
+// a window that lost sync still comes out with sync() != 0x47. Whatever is left past the
+// last whole packet is skipped and counted by remainder
+pub struct PacketIter<'a> {
+    data: &'a [u8],
+}
+
+impl Packet {
+    pub fn iter(data: &[u8]) -> PacketIter<'_> {
+        return PacketIter { data: data };
+    }
+}
+
+impl<'a> PacketIter<'a> {
+    // bytes left over after the last whole packet, non zero for a truncated capture
+    pub fn remainder(&self) -> usize {
+        return self.data.len() % 188;
+    }
+}
+
+impl<'a> Iterator for PacketIter<'a> {
+    type Item = PacketRef<'a>;
+
+    fn next(&mut self) -> Option<PacketRef<'a>> {
+        if self.data.len() < 188 {
+            return None;
+        }
+        let (pkt, rest) = self.data.split_at(188);
+        self.data = rest;
+        return Some(PacketRef(pkt));
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.data.len() / 188;
+        return (n, Some(n));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PacketIndexEntry {
+    pub offset: usize,
+    pub pid: u16,
+    pub cc: u8,
+    pub pcr: Option<u64>,
+    pub pusi: bool,
+}
+
+// single pass over a capture recording where each packet sits so tools can seek by
+// binary searching on the pcr, windows not starting with the sync byte are skipped
+pub fn build_index(buf: &[u8]) -> Vec<PacketIndexEntry> {
+    let mut index = Vec::with_capacity(buf.len() / 188);
+    for (num, window) in buf.chunks_exact(188).enumerate() {
+        let pkt = Packet::new(window.try_into().unwrap());
+        if pkt.sync() != 0x47 {
+            continue;
+        }
+        let mut pcr = None;
+        if pkt.has_adaptation_field() && pkt.aflen() > 0 && pkt.has_pcr() {
+            pcr = Some(pkt.pcr());
+        }
+        index.push(PacketIndexEntry {
+            offset: num * 188,
+            pid: pkt.pid(),
+            cc: pkt.cc(),
+            pcr: pcr,
+            pusi: pkt.pusi(),
+        });
+    }
+    return index;
+}
+
+// finds every 00 00 01 start code prefix in the payloads of packets on pid, returning the
+// index of the packet the prefix starts in and its offset within that packet's payload.
+// The trailing two payload bytes are carried over to catch prefixes split across packets
+pub fn find_start_codes(buf: &[u8], pid: u16) -> Vec<(usize, usize)> {
+    let mut found = Vec::new();
+    // (byte, packet index, payload offset) for the tail of the previous payload
+    let mut carry: Vec<(u8, usize, usize)> = Vec::new();
+    for (pkt_idx, window) in buf.chunks_exact(188).enumerate() {
+        let pkt = Packet::new(window.try_into().unwrap());
+        if pkt.sync() != 0x47 || pkt.pid() != pid || !pkt.has_payload() {
+            continue;
+        }
+        let mut bytes = carry.clone();
+        for (offset, b) in pkt.payload_data().iter().enumerate() {
+            bytes.push((*b, pkt_idx, offset));
+        }
+        for code in bytes.windows(3) {
+            if code[0].0 == 0 && code[1].0 == 0 && code[2].0 == 1 {
+                found.push((code[0].1, code[0].2));
+            }
+        }
+        let keep = bytes.len().min(2);
+        carry = bytes[bytes.len() - keep..].to_vec();
+    }
+    return found;
+}
+
+// how far resync looks for the sync pattern before giving up
+pub const RESYNC_WINDOW: usize = 188 * 3;
+
+// number of bytes to drop from the front of buf to get back onto packet boundaries, found
+// by looking for sync bytes on three consecutive 188 byte strides. Near the end of buf as
+// many as fit are checked, but a sync byte always has to be confirmed by the one a stride
+// on. 0 when already aligned, None if nothing lines up within the window
+pub fn resync(buf: &[u8]) -> Option<usize> {
+    return resync_stride(buf, 188, 0);
+}
+
+// resync for packets stride bytes apart with their sync byte sync_at bytes in
+fn resync_stride(buf: &[u8], stride: usize, sync_at: usize) -> Option<usize> {
+    for offset in 0..(3 * stride).min(buf.len()) {
+        let syncs: Vec<usize> = (0..3)
+            .map(|n| offset + sync_at + n * stride)
+            .take_while(|pos| *pos < buf.len())
+            .collect();
+        if syncs.len() >= 2 && syncs.iter().all(|pos| buf[*pos] == 0x47) {
+            return Some(offset);
+        }
+    }
+    return None;
+}
+
+// sync bytes detect_packet_size wants lined up before believing a stride
+const MIN_ALIGNED_SYNCS: usize = 5;
+
+// 188, 192 (M2TS) or 204 (with Reed-Solomon parity), whichever stride puts the most sync
+// bytes in a row starting somewhere in the first packet's worth of buf. None unless the
+// best manages MIN_ALIGNED_SYNCS, the answer being what PacketReader::with_packet_size takes
+pub fn detect_packet_size(data: &[u8]) -> Option<usize> {
+    let mut best = None;
+    let mut best_run = MIN_ALIGNED_SYNCS - 1;
+    for stride in [188, 192, 204].iter() {
+        for offset in 0..(*stride).min(data.len()) {
+            let run = data[offset..]
+                .iter()
+                .step_by(*stride)
+                .take_while(|b| **b == 0x47)
+                .count();
+            if run > best_run {
+                best = Some(*stride);
+                best_run = run;
+            }
+        }
+    }
+    return best;
+}
+
+// calls f with each packet in buf without collecting them, skipping past anything that
+// isn't on a sync byte the same way resync does
+pub fn for_each_packet<F: FnMut(&Packet)>(buf: &[u8], mut f: F) {
+    let _ = try_for_each_packet(buf, |pkt| -> Result<(), Infallible> {
+        f(pkt);
+        return Ok(());
+    });
+}
+
+// as for_each_packet, stopping at the first error f returns
+pub fn try_for_each_packet<E, F: FnMut(&Packet) -> Result<(), E>>(
+    buf: &[u8],
+    mut f: F,
+) -> Result<(), E> {
+    let mut pos = 0;
+    while pos + 188 <= buf.len() {
+        if buf[pos] != 0x47 {
+            pos += resync(&buf[pos..]).unwrap_or(RESYNC_WINDOW).max(1);
+            continue;
+        }
+        let pkt = Packet::new(buf[pos..pos + 188].try_into().unwrap());
+        f(&pkt)?;
+        pos += 188;
+    }
+    return Ok(());
+}
+
+// distinct PIDs across a capture reading only header bytes 1 and 2 of each 188 byte window
+pub fn pid_set(buf: &[u8]) -> BTreeSet<u16> {
+    let mut pids = BTreeSet::new();
+    for window in buf.chunks_exact(188) {
+        if window[0] == 0x47 {
+            pids.insert(BigEndian::read_u16(&window[1..3]) & 0x1FFF);
+        }
+    }
+    return pids;
+}
+
+// (index, a_byte, b_byte) for every position where the two packets differ
+pub fn packet_diff(a: &PacketData, b: &PacketData) -> Vec<(usize, u8, u8)> {
+    return a
+        .iter()
+        .zip(b.iter())
+        .enumerate()
+        .filter(|(_, (x, y))| x != y)
+        .map(|(i, (x, y))| (i, *x, *y))
+        .collect();
+}
+
+// renders a packet_diff one differing byte per line as "index: aa -> bb"
+pub struct PacketDiff<'a>(pub &'a [(usize, u8, u8)]);
+
+impl<'a> fmt::Display for PacketDiff<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "packets identical");
         }
-        return &self.data[offset..188];
+        for (n, (index, a, b)) in self.0.iter().enumerate() {
+            if n > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{:3}: {:02X} -> {:02X}", index, a, b)?;
+        }
+        return Ok(());
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct EsPriorityCounts {
+    // packets with elementary_stream_priority_indicator set
+    pub priority: u64,
+    // of those, the ones also flagged random access, most likely keyframe starts
+    pub likely_keyframes: u64,
+}
+
+// counts ES priority flagged packets per PID as they go past
+#[derive(Default)]
+pub struct EsPriorityCollector {
+    counts: BTreeMap<u16, EsPriorityCounts>,
+}
+
+impl EsPriorityCollector {
+    pub fn new() -> EsPriorityCollector {
+        return EsPriorityCollector {
+            counts: BTreeMap::new(),
+        };
+    }
+
+    pub fn push(&mut self, pkt: &Packet) {
+        if !pkt.es_priority() {
+            return;
+        }
+        let counts = self.counts.entry(pkt.pid()).or_default();
+        counts.priority += 1;
+        if pkt.is_random_access() {
+            counts.likely_keyframes += 1;
+        }
+    }
+
+    // only PIDs that have had a flagged packet show up
+    pub fn counts(&self) -> &BTreeMap<u16, EsPriorityCounts> {
+        return &self.counts;
+    }
+}
+
+// drops null packets from a stream, keeping count of how many went between each pair of
+// real packets so the padding can be put back later
+#[derive(Default)]
+pub struct NullStripper {
+    removed: u64,
+    since_last: u64,
+    before_last: u64,
+}
+
+impl NullStripper {
+    pub fn new() -> NullStripper {
+        return NullStripper {
+            removed: 0,
+            since_last: 0,
+            before_last: 0,
+        };
+    }
+
+    pub fn strip(&mut self, pkt: Packet) -> Option<PacketData> {
+        if pkt.pid() == NULL_PID {
+            self.removed += 1;
+            self.since_last += 1;
+            return None;
+        }
+        self.before_last = self.since_last;
+        self.since_last = 0;
+        return Some(pkt.data);
+    }
+
+    pub fn removed_count(&self) -> u64 {
+        return self.removed;
+    }
+
+    // nulls dropped between the last packet passed and the one before it
+    pub fn removed_before_last(&self) -> u64 {
+        return self.before_last;
+    }
+}
+
+// buffers bursts of live input (socket reads and the like) that needn't line up with packet
+// boundaries in a fixed ring allocated up front, handing back whole packets from drain.
+// When a burst would overflow the ring the oldest whole packets are dropped to make room,
+// so what's left stays on packet boundaries
+pub struct RingDemux {
+    ring: Vec<u8>,
+    head: usize,
+    len: usize,
+    dropped: usize,
+    // false once head has been off a sync byte, until a sync byte with another a packet on
+    // is found
+    synced: bool,
+}
+
+impl RingDemux {
+    pub fn new(capacity_packets: usize) -> RingDemux {
+        return RingDemux {
+            ring: vec![0; 188 * capacity_packets.max(1)],
+            head: 0,
+            len: 0,
+            dropped: 0,
+            synced: true,
+        };
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        let capacity = self.ring.len();
+        let mut bytes = bytes;
+        if bytes.len() > capacity {
+            let cut = (bytes.len() - capacity).div_ceil(188) * 188;
+            self.dropped += cut;
+            bytes = &bytes[cut..];
+        }
+        let overflow = (self.len + bytes.len()).saturating_sub(capacity);
+        if overflow > 0 {
+            let drop = (overflow.div_ceil(188) * 188).min(self.len);
+            self.dropped += drop;
+            self.head = (self.head + drop) % capacity;
+            self.len -= drop;
+        }
+        let tail = (self.head + self.len) % capacity;
+        let first = bytes.len().min(capacity - tail);
+        self.ring[tail..tail + first].copy_from_slice(&bytes[..first]);
+        self.ring[..bytes.len() - first].copy_from_slice(&bytes[first..]);
+        self.len += bytes.len();
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = Packet> + '_ {
+        return RingDrain { demux: self };
+    }
+
+    // bytes lost to overflow or skipped getting back onto a sync byte
+    pub fn dropped(&self) -> usize {
+        return self.dropped;
+    }
+
+    fn next_packet(&mut self) -> Option<Packet> {
+        let capacity = self.ring.len();
+        while self.len > 0 {
+            if self.ring[self.head] == 0x47 {
+                if self.synced || capacity < 2 * 188 {
+                    break;
+                }
+                // a lone 0x47 could be payload, the way resync wants a second sync byte
+                // a packet on before taking it. Wait for more input to tell
+                if self.len < 2 * 188 {
+                    return None;
+                }
+                if self.ring[(self.head + 188) % capacity] == 0x47 {
+                    self.synced = true;
+                    break;
+                }
+            }
+            self.synced = false;
+            self.head = (self.head + 1) % capacity;
+            self.len -= 1;
+            self.dropped += 1;
+        }
+        if self.len < 188 {
+            return None;
+        }
+        let mut data = [0u8; 188];
+        let first = 188.min(capacity - self.head);
+        data[..first].copy_from_slice(&self.ring[self.head..self.head + first]);
+        data[first..].copy_from_slice(&self.ring[..188 - first]);
+        self.head = (self.head + 188) % capacity;
+        self.len -= 188;
+        return Some(Packet::new(data));
+    }
+}
+
+struct RingDrain<'a> {
+    demux: &'a mut RingDemux,
+}
+
+impl<'a> Iterator for RingDrain<'a> {
+    type Item = Packet;
+
+    fn next(&mut self) -> Option<Packet> {
+        return self.demux.next_packet();
+    }
+}
+
+// what PacketReader::next_event found next in the stream
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReadEvent {
+    Packet(PacketData),
+    // bytes thrown away getting back onto packet boundaries, reported ahead of the first
+    // packet after them
+    ReSync { dropped_bytes: usize },
+}
+
+// reads packets off a byte stream 188 bytes at a time, or at the stride of M2TS or 204 byte
+// packets when built with_packet_size. When a packet doesn't start on a sync byte it reads
+// ahead a few packets' worth and realigns on three sync bytes a stride apart the way resync
+// does, so a short packet costs the packet after it and not the rest of the stream
+pub struct PacketReader<R: Read> {
+    inner: R,
+    packet_size: usize,
+    buf: Vec<u8>,
+    eof: bool,
+    skipped: u64,
+    // arrival_time_stamp of the last M2TS packet read
+    arrival_timestamp: u32,
+    trailing: usize,
+}
+
+impl<R: Read> PacketReader<R> {
+    pub fn new(inner: R) -> PacketReader<R> {
+        return PacketReader {
+            inner: inner,
+            packet_size: 188,
+            buf: Vec::new(),
+            eof: false,
+            skipped: 0,
+            arrival_timestamp: 0,
+            trailing: 0,
+        };
+    }
+
+    // reads 192 byte M2TS packets, next_timed_packet giving each one's arrival timestamp
+    pub fn new_m2ts(inner: R) -> PacketReader<R> {
+        let mut reader = PacketReader::new(inner);
+        reader.packet_size = 192;
+        return reader;
+    }
+
+    // 192 for M2TS, whose arrival timestamps are dropped, or 204 for packets followed by
+    // Reed-Solomon parity, which is dropped too. None for any other size
+    pub fn with_packet_size(inner: R, packet_size: usize) -> Option<PacketReader<R>> {
+        if packet_size != 188 && packet_size != 192 && packet_size != 204 {
+            return None;
+        }
+        let mut reader = PacketReader::new(inner);
+        reader.packet_size = packet_size;
+        return Some(reader);
+    }
+
+    // next_packet along with the 30 bit arrival_time_stamp the packet came with, an
+    // InvalidInput error unless the reader was built for M2TS
+    pub fn next_timed_packet(&mut self) -> io::Result<Option<(u32, Packet)>> {
+        if self.packet_size != 192 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only M2TS packets carry an arrival timestamp",
+            ));
+        }
+        let data = self.next_packet()?;
+        return Ok(data.map(|data| (self.arrival_timestamp, Packet::new(data))));
+    }
+
+    // next_event with the resyncs left out
+    pub fn next_packet(&mut self) -> io::Result<Option<PacketData>> {
+        loop {
+            match self.next_event()? {
+                Some(ReadEvent::Packet(data)) => return Ok(Some(data)),
+                Some(ReadEvent::ReSync { .. }) => continue,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    // None at the end of the stream, a partial packet left at the end being dropped and
+    // counted by trailing_bytes. Junk with no packet in it before the end is reported as a
+    // resync
+    pub fn next_event(&mut self) -> io::Result<Option<ReadEvent>> {
+        let size = self.packet_size;
+        // the sync byte comes after the TP_extra_header in M2TS
+        let sync_at = if size == 192 { 4 } else { 0 };
+        let mut dropped = 0;
+        loop {
+            self.fill(size)?;
+            let aligned = self.buf.len() > sync_at && self.buf[sync_at] == 0x47;
+            if dropped > 0 && (aligned || self.buf.is_empty()) {
+                return Ok(Some(ReadEvent::ReSync {
+                    dropped_bytes: dropped,
+                }));
+            }
+            if self.buf.is_empty() {
+                return Ok(None);
+            }
+            if self.buf.len() < size {
+                if aligned {
+                    self.trailing = self.buf.len();
+                    self.buf.clear();
+                    return Ok(None);
+                }
+                dropped += self.buf.len();
+                self.skipped += self.buf.len() as u64;
+                self.buf.clear();
+                continue;
+            }
+            if aligned {
+                if size == 192 {
+                    self.arrival_timestamp = BigEndian::read_u32(&self.buf[0..4]) & 0x3FFF_FFFF;
+                }
+                let data = self.buf[sync_at..sync_at + 188].try_into().unwrap();
+                self.buf.drain(..size);
+                return Ok(Some(ReadEvent::Packet(data)));
+            }
+            self.fill(5 * size)?;
+            let skip = resync_stride(&self.buf, size, sync_at)
+                .unwrap_or(self.buf.len().min(3 * size))
+                .max(1);
+            self.buf.drain(..skip);
+            dropped += skip;
+            self.skipped += skip as u64;
+        }
+    }
+
+    // bytes of a partial packet left when the stream ended, 0 for a clean end. Only set
+    // once next_event has returned None
+    pub fn trailing_bytes(&self) -> usize {
+        return self.trailing;
+    }
+
+    // bytes dropped getting onto a sync byte
+    pub fn skipped(&self) -> u64 {
+        return self.skipped;
+    }
+
+    pub fn into_inner(self) -> R {
+        return self.inner;
+    }
+
+    // reads until buf holds want bytes or the stream ends
+    fn fill(&mut self, want: usize) -> io::Result<()> {
+        let mut chunk = [0u8; 1024];
+        while self.buf.len() < want && !self.eof {
+            let needed = (want - self.buf.len()).min(chunk.len());
+            match self.inner.read(&mut chunk[..needed]) {
+                Ok(0) => self.eof = true,
+                Ok(read) => self.buf.extend_from_slice(&chunk[..read]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        return Ok(());
+    }
+}
+
+// writes packets out back to back, or as 192 byte M2TS with a 4 byte arrival timestamp
+// prefix when built with new_m2ts
+pub struct TsWriter<W: Write> {
+    inner: W,
+    m2ts_bitrate: Option<u64>,
+    packets_written: u64,
+}
+
+impl<W: Write> TsWriter<W> {
+    pub fn new(inner: W) -> TsWriter<W> {
+        return TsWriter {
+            inner: inner,
+            m2ts_bitrate: None,
+            packets_written: 0,
+        };
+    }
+
+    // arrival timestamps advance by 188 * 8 / bitrate seconds of the 27MHz clock per packet
+    pub fn new_m2ts(inner: W, bitrate_bps: u64) -> TsWriter<W> {
+        return TsWriter {
+            inner: inner,
+            m2ts_bitrate: Some(bitrate_bps.max(1)),
+            packets_written: 0,
+        };
+    }
+
+    pub fn write_packet(&mut self, data: &PacketData) -> io::Result<()> {
+        if let Some(bitrate) = self.m2ts_bitrate {
+            let ticks = self.packets_written as u128 * 188 * 8 * 27_000_000 / bitrate as u128;
+            // top 2 bits are copy_permission_indicator, left as 0
+            let mut prefix = [0u8; 4];
+            BigEndian::write_u32(&mut prefix, (ticks as u32) & 0x3FFF_FFFF);
+            self.inner.write_all(&prefix)?;
+        }
+        self.inner.write_all(data)?;
+        self.packets_written += 1;
+        return Ok(());
+    }
+
+    pub fn into_inner(self) -> W {
+        return self.inner;
     }
 }
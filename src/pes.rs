@@ -0,0 +1,712 @@
+use crate::codec::{detect_aac_framing, AacFraming};
+use crate::packet::{stuffing_only_af, AdaptationField, Packet, PacketData, PacketHeader, Payload};
+use crate::psi::StreamType;
+use byteorder::{BigEndian, ByteOrder};
+use std::convert::TryInto;
+
+// PES_scrambling_control, separate from the TS header's. Anything but 0 is left to the
+// scrambling system to define
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PesScramblingControl {
+    NotScrambled,
+    UserDefined(u8),
+}
+
+// trick_mode_control with the fields that go with each mode
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrickMode {
+    FastForward {
+        field_id: u8,
+        intra_slice_refresh: bool,
+        frequency_truncation: u8,
+    },
+    SlowMotion {
+        rep_cntrl: u8,
+    },
+    FreezeFrame {
+        field_id: u8,
+    },
+    FastReverse {
+        field_id: u8,
+        intra_slice_refresh: bool,
+        frequency_truncation: u8,
+    },
+    SlowReverse {
+        rep_cntrl: u8,
+    },
+    Reserved(u8),
+}
+
+// accessors over a PES packet starting with its 00 00 01 start code prefix
+pub trait PesHeader {
+    fn valid_start_code(&self) -> bool;
+    fn stream_id(&self) -> u8;
+    fn pes_packet_length(&self) -> u16;
+    fn has_optional_header(&self) -> bool;
+    fn scrambling_control(&self) -> PesScramblingControl;
+    fn data_alignment_indicator(&self) -> bool;
+    fn pts_dts_flags(&self) -> u8;
+    fn has_escr(&self) -> bool;
+    fn has_es_rate(&self) -> bool;
+    fn has_trick_mode(&self) -> bool;
+    fn trick_mode(&self) -> Option<TrickMode>;
+    fn has_additional_copy_info(&self) -> bool;
+    fn has_pes_crc(&self) -> bool;
+    fn has_extension(&self) -> bool;
+    fn header_data_len(&self) -> u8;
+    fn header_len(&self) -> usize;
+    fn has_private_data(&self) -> bool;
+    fn has_pack_header(&self) -> bool;
+    fn has_sequence_counter(&self) -> bool;
+    fn has_pstd_buffer(&self) -> bool;
+    fn has_extension_2(&self) -> bool;
+    fn private_data(&self) -> Option<&[u8; 16]>;
+    fn pstd_buffer(&self) -> Option<(bool, u16)>;
+    fn pts(&self) -> Option<u64>;
+    fn dts(&self) -> Option<u64>;
+}
+
+// PTS and DTS are 33 bits split 3/15/15 around marker bits over 5 bytes
+fn read_timestamp(buf: &[u8]) -> u64 {
+    let high = ((buf[0] as u64) >> 1) & 0x7;
+    let mid = (BigEndian::read_u16(&buf[1..3]) as u64) >> 1;
+    let low = (BigEndian::read_u16(&buf[3..5]) as u64) >> 1;
+    return (high << 30) | (mid << 15) | low;
+}
+
+// keeps the 4 bit prefix already in front of the timestamp
+pub fn write_timestamp(buf: &mut [u8], ts: u64) {
+    let ts = ts & 0x1_FFFF_FFFF;
+    buf[0] = (buf[0] & 0xF0) | ((ts >> 29) as u8 & 0x0E) | 0x1;
+    buf[1] = (ts >> 22) as u8;
+    buf[2] = ((ts >> 14) as u8 & 0xFE) | 0x1;
+    buf[3] = (ts >> 7) as u8;
+    buf[4] = ((ts << 1) as u8 & 0xFE) | 0x1;
+}
+
+// offset of the trick mode byte, after the timestamps, ESCR and ES rate when present
+fn trick_mode_offset(pes: &[u8]) -> usize {
+    let mut offset = 9;
+    match pes.pts_dts_flags() {
+        0x2 => offset += 5,
+        0x3 => offset += 10,
+        _ => {}
+    }
+    if pes.has_escr() {
+        offset += 6;
+    }
+    if pes.has_es_rate() {
+        offset += 3;
+    }
+    return offset;
+}
+
+// offset of the PES_extension flags byte, everything before it is optional so it has to
+// be walked past field by field
+fn extension_offset(pes: &[u8]) -> usize {
+    let mut offset = trick_mode_offset(pes);
+    if pes.has_trick_mode() {
+        offset += 1;
+    }
+    if pes.has_additional_copy_info() {
+        offset += 1;
+    }
+    if pes.has_pes_crc() {
+        offset += 2;
+    }
+    return offset;
+}
+
+// the optional header as far as both PES_header_data_length and the buffer go, so a cut
+// capture can't be read past its end
+fn optional_header(pes: &[u8]) -> &[u8] {
+    return &pes[..pes.header_len().min(pes.len())];
+}
+
+// the PES_extension flags byte, None when there's no extension or it was cut off
+fn extension_flags(pes: &[u8]) -> Option<u8> {
+    if !pes.has_optional_header() || !pes.has_extension() {
+        return None;
+    }
+    return optional_header(pes).get(extension_offset(pes)).copied();
+}
+
+fn extension_flag(pes: &[u8], mask: u8) -> bool {
+    return match extension_flags(pes) {
+        Some(flags) => 0 != flags & mask,
+        None => false,
+    };
+}
+
+// offset of the P-STD buffer field which follows the private data, pack header and
+// program packet sequence counter when those are present. None if the pack header's
+// length byte was cut off
+fn pstd_buffer_offset(pes: &[u8]) -> Option<usize> {
+    let mut offset = extension_offset(pes) + 1;
+    if pes.has_private_data() {
+        offset += 16;
+    }
+    if pes.has_pack_header() {
+        offset += 1 + *optional_header(pes).get(offset)? as usize;
+    }
+    if pes.has_sequence_counter() {
+        offset += 2;
+    }
+    return Some(offset);
+}
+
+impl PesHeader for &[u8] {
+    fn valid_start_code(&self) -> bool {
+        return self.len() >= 6 && self[0] == 0 && self[1] == 0 && self[2] == 1;
+    }
+    fn stream_id(&self) -> u8 {
+        return self[3];
+    }
+    fn pes_packet_length(&self) -> u16 {
+        return BigEndian::read_u16(&self[4..6]);
+    }
+    fn has_optional_header(&self) -> bool {
+        return match self.stream_id() {
+            // program stream map, padding, private stream 2, ECM, EMM, DSMCC, H.222.1 type E
+            // and program stream directory have no optional header
+            0xBC | 0xBE | 0xBF | 0xF0 | 0xF1 | 0xF2 | 0xF8 | 0xFF => false,
+            _ => self.len() >= 9 && (self[6] & 0xC0) == 0x80,
+        };
+    }
+    // NotScrambled as well when there's no optional header to carry the bits
+    fn scrambling_control(&self) -> PesScramblingControl {
+        if !self.has_optional_header() {
+            return PesScramblingControl::NotScrambled;
+        }
+        return match (self[6] >> 4) & 0x3 {
+            0 => PesScramblingControl::NotScrambled,
+            other => PesScramblingControl::UserDefined(other),
+        };
+    }
+    fn data_alignment_indicator(&self) -> bool {
+        return self.has_optional_header() && 0 != self[6] & 0x04;
+    }
+    fn pts_dts_flags(&self) -> u8 {
+        return (self[7] >> 6) & 0x3;
+    }
+    fn has_escr(&self) -> bool {
+        return 0 != self[7] & 0x20;
+    }
+    fn has_es_rate(&self) -> bool {
+        return 0 != self[7] & 0x10;
+    }
+    fn has_trick_mode(&self) -> bool {
+        return 0 != self[7] & 0x8;
+    }
+    fn trick_mode(&self) -> Option<TrickMode> {
+        if !self.has_optional_header() || !self.has_trick_mode() {
+            return None;
+        }
+        let offset = trick_mode_offset(self);
+        if offset >= self.len() {
+            return None;
+        }
+        let field = self[offset];
+        let field_id = (field >> 3) & 0x3;
+        let intra_slice_refresh = 0 != field & 0x4;
+        let frequency_truncation = field & 0x3;
+        let rep_cntrl = field & 0x1F;
+        return Some(match field >> 5 {
+            0 => TrickMode::FastForward {
+                field_id: field_id,
+                intra_slice_refresh: intra_slice_refresh,
+                frequency_truncation: frequency_truncation,
+            },
+            1 => TrickMode::SlowMotion {
+                rep_cntrl: rep_cntrl,
+            },
+            2 => TrickMode::FreezeFrame { field_id: field_id },
+            3 => TrickMode::FastReverse {
+                field_id: field_id,
+                intra_slice_refresh: intra_slice_refresh,
+                frequency_truncation: frequency_truncation,
+            },
+            4 => TrickMode::SlowReverse {
+                rep_cntrl: rep_cntrl,
+            },
+            other => TrickMode::Reserved(other),
+        });
+    }
+    fn has_additional_copy_info(&self) -> bool {
+        return 0 != self[7] & 0x4;
+    }
+    fn has_pes_crc(&self) -> bool {
+        return 0 != self[7] & 0x2;
+    }
+    fn has_extension(&self) -> bool {
+        return 0 != self[7] & 0x1;
+    }
+    fn header_data_len(&self) -> u8 {
+        return self[8];
+    }
+    // bytes from the start code up to the first elementary stream byte
+    fn header_len(&self) -> usize {
+        if self.has_optional_header() {
+            return 9 + self.header_data_len() as usize;
+        }
+        return 6;
+    }
+    fn has_private_data(&self) -> bool {
+        return extension_flag(self, 0x80);
+    }
+    fn has_pack_header(&self) -> bool {
+        return extension_flag(self, 0x40);
+    }
+    fn has_sequence_counter(&self) -> bool {
+        return extension_flag(self, 0x20);
+    }
+    fn has_pstd_buffer(&self) -> bool {
+        return extension_flag(self, 0x10);
+    }
+    fn has_extension_2(&self) -> bool {
+        return extension_flag(self, 0x1);
+    }
+
+    fn private_data(&self) -> Option<&[u8; 16]> {
+        if !self.has_private_data() {
+            return None;
+        }
+        let offset = extension_offset(self) + 1;
+        return optional_header(self)
+            .get(offset..offset + 16)?
+            .try_into()
+            .ok();
+    }
+
+    fn pts(&self) -> Option<u64> {
+        if self.has_optional_header() && 0 != self.pts_dts_flags() & 0x2 && self.len() >= 14 {
+            return Some(read_timestamp(&self[9..14]));
+        }
+        return None;
+    }
+    fn dts(&self) -> Option<u64> {
+        if self.has_optional_header() && self.pts_dts_flags() == 0x3 && self.len() >= 19 {
+            return Some(read_timestamp(&self[14..19]));
+        }
+        return None;
+    }
+
+    // (P-STD_buffer_scale, P-STD_buffer_size)
+    fn pstd_buffer(&self) -> Option<(bool, u16)> {
+        if !self.has_pstd_buffer() {
+            return None;
+        }
+        let offset = pstd_buffer_offset(self)?;
+        let field = BigEndian::read_u16(optional_header(self).get(offset..offset + 2)?);
+        return Some((0 != field & 0x2000, field & 0x1FFF));
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PesError {
+    // data_alignment_indicator was set but the payload doesn't open on a start code/syncword
+    MisalignedPes { stream_id: u8 },
+    // a unit should have started but the payload doesn't open with 00 00 01
+    MissingStartCode,
+    // more bytes turned up for the unit than its PES_packet_length allows
+    LengthMismatch { declared: usize, actual: usize },
+    // a packet went missing in the middle of a unit
+    CcDiscontinuity { expected: u8, found: u8 },
+    // the next unit started before this one had all of its PES_packet_length
+    Truncated { declared: usize, received: usize },
+}
+
+// when the PES claims data alignment, checks its payload opens where the stream type says it
+// should: a start code for video, the ADTS or LOAS syncword for AAC. Other stream types
+// aren't checked
+pub fn check_alignment(pes: &[u8], stream_type: StreamType) -> Result<(), PesError> {
+    if !pes.valid_start_code() || !pes.data_alignment_indicator() {
+        return Ok(());
+    }
+    let payload = &pes[pes.header_len().min(pes.len())..];
+    let aligned = match stream_type {
+        StreamType::Mpeg1Video
+        | StreamType::Mpeg2Video
+        | StreamType::Mpeg4Video
+        | StreamType::H264
+        | StreamType::H265
+        | StreamType::Vc1 => payload.len() >= 3 && payload[0..3] == [0, 0, 1],
+        StreamType::AdtsAac => detect_aac_framing(payload) == AacFraming::Adts,
+        StreamType::LatmAac => detect_aac_framing(payload) == AacFraming::Loas,
+        _ => true,
+    };
+    if !aligned {
+        return Err(PesError::MisalignedPes {
+            stream_id: pes.stream_id(),
+        });
+    }
+    return Ok(());
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TimestampViolation {
+    DtsRegression { previous: u64, dts: u64 },
+    PtsBeforeDts { pts: u64, dts: u64 },
+}
+
+// true when b comes before a on the 33 bit timeline, allowing for wrap
+fn before(a: u64, b: u64) -> bool {
+    return b.wrapping_sub(a) & 0x1_FFFF_FFFF > 1 << 32;
+}
+
+// checks the PES units of one stream keep DTS non-decreasing and PTS no earlier than DTS.
+// A PES with only a PTS is taken to have a DTS equal to it
+#[derive(Default)]
+pub struct TimestampOrderChecker {
+    last_dts: Option<u64>,
+}
+
+impl TimestampOrderChecker {
+    pub fn new() -> TimestampOrderChecker {
+        return TimestampOrderChecker { last_dts: None };
+    }
+
+    pub fn check(&mut self, pes: &[u8]) -> Vec<TimestampViolation> {
+        let mut violations = Vec::new();
+        let pts = match pes.pts() {
+            Some(pts) if pes.valid_start_code() => pts,
+            _ => return violations,
+        };
+        let dts = pes.dts().unwrap_or(pts);
+        if let Some(previous) = self.last_dts {
+            if before(previous, dts) {
+                violations.push(TimestampViolation::DtsRegression {
+                    previous: previous,
+                    dts: dts,
+                });
+            }
+        }
+        if before(dts, pts) {
+            violations.push(TimestampViolation::PtsBeforeDts { pts: pts, dts: dts });
+        }
+        self.last_dts = Some(dts);
+        return violations;
+    }
+}
+
+// total bytes a PES declares from its start code on, 0 when unbounded
+fn declared_len(pes: &[u8]) -> usize {
+    let len = pes.pes_packet_length() as usize;
+    if len == 0 {
+        return 0;
+    }
+    return 6 + len;
+}
+
+// one whole PES packet, header included
+#[derive(Debug, Clone, PartialEq)]
+pub struct PesUnit {
+    pub pid: u16,
+    pub data: Vec<u8>,
+}
+
+impl PesUnit {
+    // the elementary stream bytes after the PES header
+    pub fn payload(&self) -> &[u8] {
+        let data = self.data.as_slice();
+        return &data[data.header_len().min(data.len())..];
+    }
+}
+
+// reassembles the PES packets on one PID. Units declaring a PES_packet_length are handed
+// back as soon as that many bytes are in, unbounded ones when the next unit starts. Only
+// video may leave the length at 0 in a TS, and usually does since a frame can outgrow the
+// 16 bit field; audio always declares its length so never waits on the next PUSI. A dropped
+// unit is reported with why it was dropped
+pub struct PesAssembler {
+    pid: u16,
+    data: Vec<u8>,
+    in_unit: bool,
+    last_cc: Option<u8>,
+}
+
+impl PesAssembler {
+    pub fn new(pid: u16) -> PesAssembler {
+        return PesAssembler {
+            pid: pid,
+            data: Vec::new(),
+            in_unit: false,
+            last_cc: None,
+        };
+    }
+
+    pub fn push(&mut self, pkt: &Packet) -> Result<Option<PesUnit>, PesError> {
+        if pkt.pid() != self.pid || !pkt.has_payload() {
+            return Ok(None);
+        }
+        let cc = pkt.cc();
+        let expected = self.last_cc.map(|last| (last + 1) & 0xF);
+        if self.last_cc == Some(cc) {
+            // a repeated packet carries nothing new
+            return Ok(None);
+        }
+        self.last_cc = Some(cc);
+        let payload = pkt.payload_data();
+        if pkt.pusi() {
+            let previous = self.take_unit();
+            self.in_unit = payload.valid_start_code();
+            if self.in_unit {
+                self.data.extend_from_slice(payload);
+            }
+            if let Some(expected) = expected.filter(|expected| *expected != cc) {
+                if previous.is_some() {
+                    return Err(PesError::CcDiscontinuity {
+                        expected: expected,
+                        found: cc,
+                    });
+                }
+            }
+            if let Some(previous) = previous {
+                // a bounded unit would already have been handed back if it were whole
+                let declared = declared_len(&previous);
+                if declared > 0 {
+                    return Err(PesError::Truncated {
+                        declared: declared,
+                        received: previous.len(),
+                    });
+                }
+                // the unbounded unit is returned even if the new one lacks a start code
+                return Ok(Some(PesUnit {
+                    pid: self.pid,
+                    data: previous,
+                }));
+            }
+            if !self.in_unit {
+                return Err(PesError::MissingStartCode);
+            }
+            return self.complete_if_whole();
+        }
+        if !self.in_unit {
+            return Ok(None);
+        }
+        if let Some(expected) = expected.filter(|expected| *expected != cc) {
+            self.take_unit();
+            return Err(PesError::CcDiscontinuity {
+                expected: expected,
+                found: cc,
+            });
+        }
+        self.data.extend_from_slice(payload);
+        return self.complete_if_whole();
+    }
+
+    // the unbounded unit still waiting on the next PUSI, for when the input runs out. A
+    // bounded unit that never got all of its bytes is dropped
+    pub fn flush(&mut self) -> Option<PesUnit> {
+        let data = self.take_unit()?;
+        if declared_len(&data) > 0 {
+            return None;
+        }
+        return Some(PesUnit {
+            pid: self.pid,
+            data: data,
+        });
+    }
+
+    fn take_unit(&mut self) -> Option<Vec<u8>> {
+        let was_in_unit = self.in_unit;
+        self.in_unit = false;
+        let data = std::mem::take(&mut self.data);
+        if was_in_unit {
+            return Some(data);
+        }
+        return None;
+    }
+
+    fn complete_if_whole(&mut self) -> Result<Option<PesUnit>, PesError> {
+        let declared = declared_len(&self.data);
+        if declared == 0 || self.data.len() < declared {
+            return Ok(None);
+        }
+        let data = self.take_unit().unwrap_or_default();
+        if data.len() > declared {
+            return Err(PesError::LengthMismatch {
+                declared: declared,
+                actual: data.len(),
+            });
+        }
+        return Ok(Some(PesUnit {
+            pid: self.pid,
+            data: data,
+        }));
+    }
+}
+
+// somewhere a player can start decoding from, byte_offset being where the PUSI packet of
+// the access unit sits among the packets pushed and pcr the last PCR seen at that point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SeekPoint {
+    pub pcr: Option<u64>,
+    pub byte_offset: u64,
+    pub is_idr: bool,
+}
+
+struct UnitStart {
+    byte_offset: u64,
+    random_access: bool,
+    pcr: Option<u64>,
+}
+
+// indexes the access units on one video PID that decoding can start from: those flagged
+// random_access_indicator whose first picture is an H.264 IDR, or for H.265 any IRAP picture
+// with is_idr only set for the IDR ones. Other stream types go by random_access_indicator
+// alone. Expects one access unit per PES as video is nearly always carried, and since an
+// unbounded PES only completes when the next starts the last one pushed is never indexed
+pub struct SeekIndexBuilder {
+    pid: u16,
+    pcr_pid: u16,
+    stream_type: StreamType,
+    assembler: PesAssembler,
+    offset: u64,
+    last_pcr: Option<u64>,
+    current: Option<UnitStart>,
+    previous: Option<UnitStart>,
+    points: Vec<SeekPoint>,
+}
+
+impl SeekIndexBuilder {
+    pub fn new(pid: u16, pcr_pid: u16, stream_type: StreamType) -> SeekIndexBuilder {
+        return SeekIndexBuilder {
+            pid: pid,
+            pcr_pid: pcr_pid,
+            stream_type: stream_type,
+            assembler: PesAssembler::new(pid),
+            offset: 0,
+            last_pcr: None,
+            current: None,
+            previous: None,
+            points: Vec::new(),
+        };
+    }
+
+    // every packet of the stream, not only those on the video PID, so offsets and PCRs line up
+    pub fn push(&mut self, pkt: &Packet) {
+        let offset = self.offset;
+        self.offset += 188;
+        if pkt.pid() == self.pcr_pid && pkt.has_pcr() {
+            self.last_pcr = Some(pkt.pcr());
+        }
+        if pkt.pid() != self.pid {
+            return;
+        }
+        if pkt.pusi() && pkt.has_payload() {
+            self.previous = self.current.take();
+            self.current = Some(UnitStart {
+                byte_offset: offset,
+                random_access: pkt.is_random_access(),
+                pcr: self.last_pcr,
+            });
+        }
+        let unit = match self.assembler.push(pkt) {
+            Ok(Some(unit)) => unit,
+            // dropped units are left out of the index
+            _ => return,
+        };
+        // an unbounded unit is only handed back once the next one has started
+        let start = if pkt.pusi() && declared_len(&unit.data) == 0 {
+            self.previous.take()
+        } else {
+            self.current.take()
+        };
+        if let Some(start) = start.filter(|start| start.random_access) {
+            if let Some(is_idr) = self.random_access_picture(unit.payload()) {
+                self.points.push(SeekPoint {
+                    pcr: start.pcr,
+                    byte_offset: start.byte_offset,
+                    is_idr: is_idr,
+                });
+            }
+        }
+    }
+
+    pub fn points(&self) -> &[SeekPoint] {
+        return &self.points;
+    }
+
+    // Some(is_idr) when the access unit starts with a picture decoding can begin on
+    fn random_access_picture(&self, es: &[u8]) -> Option<bool> {
+        return match self.stream_type {
+            StreamType::H264 => match first_vcl_nal_type(es, false) {
+                Some(5) => Some(true),
+                _ => None,
+            },
+            // IDR_W_RADL and IDR_N_LP, then BLA and CRA
+            StreamType::H265 => match first_vcl_nal_type(es, true) {
+                Some(19..=20) => Some(true),
+                Some(16..=21) => Some(false),
+                _ => None,
+            },
+            _ => Some(false),
+        };
+    }
+}
+
+// nal_unit_type of the first slice in an Annex B access unit, skipping the AUD, parameter
+// sets and SEI ahead of it. H.264 slices are types 1-5 and H.265 ones 0-31
+fn first_vcl_nal_type(es: &[u8], hevc: bool) -> Option<u8> {
+    let mut pos = 0;
+    while pos + 3 < es.len() {
+        if es[pos] != 0 || es[pos + 1] != 0 || es[pos + 2] != 1 {
+            pos += 1;
+            continue;
+        }
+        let header = es[pos + 3];
+        if hevc && (header >> 1) & 0x3F < 32 {
+            return Some((header >> 1) & 0x3F);
+        }
+        if !hevc && (1..=5).contains(&(header & 0x1F)) {
+            return Some(header & 0x1F);
+        }
+        pos += 3;
+    }
+    return None;
+}
+
+// reassembles the PES packets on pid and concatenates their payloads with the PES headers
+// stripped, giving the raw elementary stream (Annex B for H.264). Anything before the
+// first PES start is skipped, as is any unit PesAssembler drops, so a lost packet costs the
+// unit it was in rather than splicing the units either side together
+pub fn extract_es(buf: &[u8], pid: u16) -> Vec<u8> {
+    let mut es = Vec::new();
+    let mut assembler = PesAssembler::new(pid);
+    for window in buf.chunks_exact(188) {
+        let pkt = Packet::new(window.try_into().unwrap());
+        if pkt.sync() != 0x47 {
+            continue;
+        }
+        if let Ok(Some(unit)) = assembler.push(&pkt) {
+            es.extend_from_slice(unit.payload());
+        }
+    }
+    if let Some(unit) = assembler.flush() {
+        es.extend_from_slice(unit.payload());
+    }
+    return es;
+}
+
+// splits a PES packet over TS packets on pid, the first starting the unit. The final
+// packet, when short, is padded out in front of its payload with a stuffing-only
+// adaptation field
+pub fn packetize_pes(pid: u16, pes: &[u8], first_cc: u8) -> Vec<PacketData> {
+    let mut packets = Vec::new();
+    for (n, chunk) in pes.chunks(184).enumerate() {
+        let cc = first_cc.wrapping_add(n as u8) & 0xF;
+        let mut afc = 1;
+        let mut af = Vec::new();
+        if chunk.len() < 184 {
+            afc = 3;
+            af = stuffing_only_af((183 - chunk.len()) as u8);
+        }
+        let mut data = Packet::create_packet(false, n == 0, false, pid, 0, afc, cc);
+        let offset = 4 + af.len();
+        data[4..offset].copy_from_slice(&af);
+        data[offset..].copy_from_slice(chunk);
+        packets.push(data);
+    }
+    return packets;
+}
@@ -1,19 +1,39 @@
-use crate::packet::{Packet, PacketData, PacketHeader, Payload};
+use crate::descriptor::{
+    ca_descriptor, descriptors, Descriptor, AC3_TAG, SUBTITLING_TAG, TELETEXT_TAG,
+};
+use crate::packet::{Packet, PacketData, PacketError, PacketHeader, PacketRef, Payload};
 use byteorder::{BigEndian, ByteOrder};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::convert::TryInto;
+use std::fmt;
 
 pub trait PSI {
     fn tables(&self) -> Option<&[u8]>;
 }
 
+// the payload after the pointer_field and the bytes it skips, None when there's no payload
+// to hold a pointer_field or it points past the end of the packet
+fn pointed_tables<P: PacketHeader + Payload>(pkt: &P) -> Option<&[u8]> {
+    if !pkt.has_payload() {
+        return None;
+    }
+    let data = pkt.payload_data();
+    let padding = *data.first()? as usize;
+    if padding + 1 >= data.len() {
+        return None;
+    }
+    return Some(&data[padding + 1..]);
+}
+
 impl PSI for Packet {
     fn tables(&self) -> Option<&[u8]> {
-        if self.has_payload() {
-            let data = self.payload_data();
-            let padding = data[0] as usize;
-            //println!("table padding {}", padding + 1);
-            return Some(&data[padding + 1..]);
-        }
-        return None;
+        return pointed_tables(self);
+    }
+}
+
+impl<'a> PSI for PacketRef<'a> {
+    fn tables(&self) -> Option<&[u8]> {
+        return pointed_tables(self);
     }
 }
 
@@ -24,6 +44,7 @@ pub trait TableHeader {
     fn section_length(&self) -> u16;
     fn section_data(&self) -> &[u8];
     fn next(&self) -> Option<&[u8]>;
+    fn next_validated(&self) -> Option<&[u8]>;
 }
 
 impl TableHeader for &[u8] {
@@ -53,6 +74,24 @@ impl TableHeader for &[u8] {
         }
         return None;
     }
+    // as next, but only stepping past a long form section whose CRC checks out so a wrong
+    // section_length can't walk off into garbage
+    fn next_validated(&self) -> Option<&[u8]> {
+        if self.len() < 3 {
+            return None;
+        }
+        let section_end = 3 + self.section_length() as usize;
+        if self.has_syntax_section() {
+            if section_end < 12 || self.len() < section_end {
+                return None;
+            }
+            let section = &self[..section_end];
+            if calc_crc32(section) != section.crc32() {
+                return None;
+            }
+        }
+        return self.next();
+    }
 }
 
 pub trait TableSyntaxSection {
@@ -64,6 +103,7 @@ pub trait TableSyntaxSection {
     fn last_section_num(&self) -> u8;
     fn table_data(&self) -> &[u8];
     fn crc32(&self) -> u32;
+    fn is_complete(&self, declared_len: u16) -> bool;
 }
 
 impl TableSyntaxSection for &[u8] {
@@ -95,6 +135,356 @@ impl TableSyntaxSection for &[u8] {
         let crc_idx = table_len - 4;
         return BigEndian::read_u32(&self[crc_idx..table_len]);
     }
+    // whether the bytes on hand run to the end of a section declaring declared_len as its
+    // section_length, false means this is only a fragment still needing reassembly
+    fn is_complete(&self, declared_len: u16) -> bool {
+        return self.len() >= 3 + declared_len as usize;
+    }
+}
+
+// reassembles the sections carried on one PID as its packets come in. Sections are built up
+// in a buffer the caller provides so a single allocation can be reused for every section
+pub struct SectionAssembler {
+    in_section: bool,
+    filled: usize,
+    expected: usize,
+    max_section_size: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SectionError {
+    // section_length claimed more than max_section_size, the section was abandoned
+    SectionTooLarge { size: usize, max: usize },
+}
+
+// a private section may be up to 4096 bytes including its header
+pub const DEFAULT_MAX_SECTION_SIZE: usize = 4096;
+
+impl Default for SectionAssembler {
+    fn default() -> SectionAssembler {
+        return SectionAssembler::new();
+    }
+}
+
+impl SectionAssembler {
+    pub fn new() -> SectionAssembler {
+        return SectionAssembler::with_max_section_size(DEFAULT_MAX_SECTION_SIZE);
+    }
+
+    pub fn with_max_section_size(max_section_size: usize) -> SectionAssembler {
+        return SectionAssembler {
+            in_section: false,
+            filled: 0,
+            expected: 0,
+            max_section_size: max_section_size,
+        };
+    }
+
+    // feeds the next packet on the PID, calling on_section with each section it completes.
+    // A section that won't fit in scratch is dropped, one claiming to be bigger than
+    // max_section_size is dropped with an error and the rest of the packet skipped
+    pub fn push_into<F: FnMut(&[u8])>(
+        &mut self,
+        pkt: &Packet,
+        scratch: &mut [u8],
+        mut on_section: F,
+    ) -> Result<(), SectionError> {
+        if !pkt.has_payload() {
+            return Ok(());
+        }
+        let payload = pkt.payload_data();
+        if !pkt.pusi() {
+            if self.in_section {
+                self.continue_section(payload, scratch, &mut on_section)?;
+            }
+            return Ok(());
+        }
+        if payload.is_empty() {
+            return Ok(());
+        }
+        let after_pointer = &payload[1..];
+        let pointer = (payload[0] as usize).min(after_pointer.len());
+        // the bytes ahead of the pointer finish off whatever section was in progress
+        if self.in_section {
+            let finished =
+                self.continue_section(&after_pointer[..pointer], scratch, &mut on_section);
+            self.reset();
+            finished?;
+        }
+        let mut rest = &after_pointer[pointer..];
+        // 0xFF where a table_id would be is stuffing to the end of the packet
+        while !rest.is_empty() && rest[0] != 0xFF {
+            self.in_section = true;
+            let used = self.continue_section(rest, scratch, &mut on_section)?;
+            rest = &rest[used..];
+            if self.in_section {
+                break;
+            }
+        }
+        return Ok(());
+    }
+
+    // convenience over push_into that hands back each completed section as its own Vec,
+    // oversized sections are dropped without the error
+    pub fn push(&mut self, pkt: &Packet, scratch: &mut [u8]) -> Vec<Vec<u8>> {
+        let mut sections = Vec::new();
+        let _ = self.push_into(pkt, scratch, |section| sections.push(section.to_vec()));
+        return sections;
+    }
+
+    fn reset(&mut self) {
+        self.in_section = false;
+        self.filled = 0;
+        self.expected = 0;
+    }
+
+    // appends to the section in progress, emitting it once complete, returns the bytes used
+    fn continue_section<F: FnMut(&[u8])>(
+        &mut self,
+        bytes: &[u8],
+        scratch: &mut [u8],
+        on_section: &mut F,
+    ) -> Result<usize, SectionError> {
+        let mut used = 0;
+        while used < bytes.len() {
+            let need = if self.filled < 3 {
+                3 - self.filled
+            } else {
+                self.expected - self.filled
+            };
+            let take = need.min(bytes.len() - used);
+            if self.filled + take > scratch.len() {
+                self.reset();
+                return Ok(bytes.len());
+            }
+            scratch[self.filled..self.filled + take].copy_from_slice(&bytes[used..used + take]);
+            self.filled += take;
+            used += take;
+            if self.filled == 3 && self.expected == 0 {
+                // private sections can use all 12 bits of section_length
+                self.expected = 3 + (0xFFF & BigEndian::read_u16(&scratch[1..3])) as usize;
+                if self.expected > self.max_section_size {
+                    let size = self.expected;
+                    self.reset();
+                    return Err(SectionError::SectionTooLarge {
+                        size: size,
+                        max: self.max_section_size,
+                    });
+                }
+            }
+            if self.filled >= 3 && self.filled == self.expected {
+                on_section(&scratch[..self.expected]);
+                self.reset();
+                return Ok(used);
+            }
+        }
+        return Ok(used);
+    }
+}
+
+// every section completed on the PIDs asked for, in the order they finish. Long form sections
+// with a bad CRC are dropped, short form ones have no CRC to check and are passed through
+pub struct Sections<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    // each PID's assembler with the scratch its partial section is kept in
+    assemblers: BTreeMap<u16, (SectionAssembler, Vec<u8>)>,
+    ready: VecDeque<(u16, Vec<u8>)>,
+}
+
+// the single entry point for every table in a capture: runs a SectionAssembler per PID
+pub fn sections<'a>(buf: &'a [u8], psi_pids: &[u16]) -> Sections<'a> {
+    return Sections {
+        buf: buf,
+        pos: 0,
+        assemblers: psi_pids
+            .iter()
+            .map(|pid| {
+                let scratch = vec![0u8; DEFAULT_MAX_SECTION_SIZE];
+                return (*pid, (SectionAssembler::new(), scratch));
+            })
+            .collect(),
+        ready: VecDeque::new(),
+    };
+}
+
+impl<'a> Iterator for Sections<'a> {
+    type Item = (u16, Vec<u8>);
+
+    fn next(&mut self) -> Option<(u16, Vec<u8>)> {
+        while self.ready.is_empty() && self.pos + 188 <= self.buf.len() {
+            let window = &self.buf[self.pos..self.pos + 188];
+            self.pos += 188;
+            let pkt = Packet::new(window.try_into().unwrap());
+            if pkt.sync() != 0x47 {
+                continue;
+            }
+            let pid = pkt.pid();
+            let (assembler, scratch) = match self.assemblers.get_mut(&pid) {
+                Some(assembler) => assembler,
+                None => continue,
+            };
+            let ready = &mut self.ready;
+            let _ = assembler.push_into(&pkt, scratch, |section| {
+                if section.has_syntax_section()
+                    && (section.len() < 12 || calc_crc32(section) != section.crc32())
+                {
+                    return;
+                }
+                ready.push_back((pid, section.to_vec()));
+            });
+        }
+        return self.ready.pop_front();
+    }
+}
+
+// CRC checks for PSI that repeats unchanged every few hundred ms: the last section checked on
+// each PID is remembered along with its result, and a section identical to it gets the same
+// answer without the CRC being run again. A cheap hash rules out most changed sections
+// before the bytes are compared
+#[derive(Default)]
+pub struct CachedValidator {
+    // hash, bytes and result of the last section validated per PID
+    last: BTreeMap<u16, (u64, Vec<u8>, bool)>,
+    crc_computations: u64,
+}
+
+impl CachedValidator {
+    pub fn new() -> CachedValidator {
+        return CachedValidator {
+            last: BTreeMap::new(),
+            crc_computations: 0,
+        };
+    }
+
+    // whether a long form section's CRC checks out, short ones are never valid
+    pub fn validate(&mut self, pid: u16, section: &[u8]) -> bool {
+        let hash = section_hash(section);
+        if let Some((last_hash, last_section, valid)) = self.last.get(&pid) {
+            if *last_hash == hash && last_section.as_slice() == section {
+                return *valid;
+            }
+        }
+        self.crc_computations += 1;
+        let valid = section.len() >= 12 && calc_crc32(section) == section.crc32();
+        self.last.insert(pid, (hash, section.to_vec(), valid));
+        return valid;
+    }
+
+    // times validate had to run the CRC rather than answer from the cache
+    pub fn crc_computations(&self) -> u64 {
+        return self.crc_computations;
+    }
+}
+
+// FNV-1a, enough to tell changed sections apart without the cost of a CRC
+fn section_hash(section: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in section.iter() {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    return hash;
+}
+
+// gathers the sections 0..=last_section_number making up one version of a table
+pub struct TableCollector {
+    table_id: u8,
+    table_id_ext: u16,
+    version: u8,
+    last_section_num: u8,
+    sections: BTreeMap<u8, Vec<u8>>,
+}
+
+impl TableCollector {
+    pub fn new(table_id: u8) -> TableCollector {
+        return TableCollector {
+            table_id: table_id,
+            table_id_ext: 0,
+            version: 0,
+            last_section_num: 0,
+            sections: BTreeMap::new(),
+        };
+    }
+
+    // sections for other tables, not yet current or with a bad CRC are ignored, a new
+    // version or table_id_ext starts collecting over. Returns true once the table is complete
+    pub fn add_section(&mut self, section: &[u8]) -> bool {
+        if section.len() < 12
+            || section.table_id() != self.table_id
+            || !section.has_syntax_section()
+            || !section.valid_syntax()
+            || !section.current()
+            || calc_crc32(section) != section.crc32()
+        {
+            return self.is_complete();
+        }
+        if section.table_id_ext() != self.table_id_ext
+            || section.version() != self.version
+            || section.last_section_num() != self.last_section_num
+        {
+            self.sections.clear();
+            self.table_id_ext = section.table_id_ext();
+            self.version = section.version();
+            self.last_section_num = section.last_section_num();
+        }
+        if section.section_num() <= self.last_section_num {
+            self.sections
+                .insert(section.section_num(), section.to_vec());
+        }
+        return self.is_complete();
+    }
+
+    pub fn is_complete(&self) -> bool {
+        return !self.sections.is_empty()
+            && self.sections.len() == self.last_section_num as usize + 1;
+    }
+
+    // the sections collected so far, in section_number order
+    pub fn sections(&self) -> Vec<&[u8]> {
+        return self.sections.values().map(|s| s.as_slice()).collect();
+    }
+}
+
+// splits one logical table into numbered sections of at most max_section_bytes each, CRC
+// included. full_payload is the 8 byte long form header followed by the whole table body
+// with no CRC; the header is repeated on every section with section_number and
+// last_section_number filled in and the body cut at byte boundaries. Empty if the header
+// is short, max_section_bytes leaves no room for a body or more than 256 sections are needed
+pub fn segment_table(full_payload: &[u8], max_section_bytes: u16) -> Vec<Vec<u8>> {
+    let max = (max_section_bytes as usize).min(4096);
+    if full_payload.len() < 8 || max <= 12 {
+        return Vec::new();
+    }
+    let header = &full_payload[..8];
+    let body = &full_payload[8..];
+    let room = max - 12;
+    let count = if body.is_empty() {
+        1
+    } else {
+        body.len().div_ceil(room)
+    };
+    if count > 256 {
+        return Vec::new();
+    }
+    let mut sections = Vec::with_capacity(count);
+    for num in 0..count {
+        let chunk = &body[(num * room).min(body.len())..((num + 1) * room).min(body.len())];
+        let mut section = header.to_vec();
+        section[6] = num as u8;
+        section[7] = (count - 1) as u8;
+        section.extend_from_slice(chunk);
+        section.extend_from_slice(&[0; 4]);
+        // section_length counts everything after the length field, CRC included
+        let section_len = section.len() - 3;
+        section[1] = (section[1] & 0xF0) | (section_len >> 8) as u8;
+        section[2] = section_len as u8;
+        let crc = calc_crc32(&section);
+        let end = section.len();
+        BigEndian::write_u32(&mut section[end - 4..], crc);
+        sections.push(section);
+    }
+    return sections;
 }
 
 pub fn create_pat_packet(pids: &[u16], cc: u8) -> PacketData {
@@ -114,6 +504,30 @@ pub fn create_pat_packet(pids: &[u16], cc: u8) -> PacketData {
     return pat;
 }
 
+// a PAT as a single section listing the (program_number, pmt_pid) pairs given, split over
+// packets on PID 0 with cc counting up from the one given
+pub fn create_pat_packet_with_programs(
+    tsid: u16,
+    programs: &[(u16, u16)],
+    cc: u8,
+) -> Vec<PacketData> {
+    let mut section = vec![0, 0, 0];
+    section.extend_from_slice(&tsid.to_be_bytes());
+    section.extend_from_slice(&[0xC1, 0, 0]); // version 0 and current, section 0 of 0
+    for (program_num, pmt_pid) in programs.iter() {
+        section.extend_from_slice(&program_num.to_be_bytes());
+        section.extend_from_slice(&(0xE000 | pmt_pid).to_be_bytes());
+    }
+    let section_len = section.len() + 4 - 3;
+    section[1] = 0xB0 | (section_len >> 8) as u8;
+    section[2] = section_len as u8;
+    section.extend_from_slice(&[0; 4]);
+    let crc = calc_crc32(&section);
+    let crc_idx = section.len() - 4;
+    BigEndian::write_u32(&mut section[crc_idx..], crc);
+    return section_packets(0, &section, cc);
+}
+
 fn insert_pat_payload(offset: usize, num: usize, pid: u16, pat: &mut PacketData) {
     pat[offset] = 0; // table id
     pat[offset + 1] = 0x80 | 0x30; // section syntax & reserved bits
@@ -139,6 +553,16 @@ pub trait PAT {
     fn program_num(&self) -> u16;
     fn program_map_pid(&self) -> u16;
     fn next_program(&self) -> Option<&[u8]>;
+    fn network_pid(&self) -> Option<u16>;
+    fn programs(&self) -> impl Iterator<Item = PatEntry> + '_;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PatEntry {
+    // program_number and the PID of its PMT
+    Program(u16, u16),
+    // program_number 0, the PID carrying the NIT
+    Network(u16),
 }
 
 impl PAT for &[u8] {
@@ -157,6 +581,713 @@ impl PAT for &[u8] {
         }
         return None;
     }
+    // these two walk every entry from this one to the end of the slice, so work on the whole
+    // PAT table data
+    fn network_pid(&self) -> Option<u16> {
+        return self.programs().find_map(|entry| match entry {
+            PatEntry::Network(pid) => Some(pid),
+            PatEntry::Program(..) => None,
+        });
+    }
+    fn programs(&self) -> impl Iterator<Item = PatEntry> + '_ {
+        return self.chunks_exact(4).map(|entry| {
+            if entry.program_num() == 0 {
+                return PatEntry::Network(entry.program_map_pid());
+            }
+            return PatEntry::Program(entry.program_num(), entry.program_map_pid());
+        });
+    }
+}
+
+// scans PID 0 packets until every section of the PAT has been seen with a good CRC and
+// returns the merged (program_number, pmt_pid) list across all of them. Sections are
+// reassembled first so ones spanning packets count too
+pub fn find_pat(buf: &[u8]) -> Option<Vec<(u16, u16)>> {
+    let mut assembler = SectionAssembler::new();
+    let mut scratch = vec![0u8; DEFAULT_MAX_SECTION_SIZE];
+    let mut collector = TableCollector::new(0);
+    for window in buf.chunks_exact(188) {
+        let pkt = Packet::new(window.try_into().unwrap());
+        if pkt.sync() != 0x47 || pkt.pid() != 0 {
+            continue;
+        }
+        for section in assembler.push(&pkt, &mut scratch) {
+            if collector.add_section(&section) {
+                let mut programs = Vec::new();
+                for section in collector.sections() {
+                    programs.extend(pat_programs(section.table_data()));
+                }
+                return Some(programs);
+            }
+        }
+    }
+    return None;
+}
+
+fn pat_programs(pat: &[u8]) -> Vec<(u16, u16)> {
+    let mut programs = Vec::new();
+    for entry in pat.chunks_exact(4) {
+        programs.push((entry.program_num(), entry.program_map_pid()));
+    }
+    return programs;
+}
+
+// programs listed across every section of the PAT, not counting the network PID entry
+pub fn program_count(buf: &[u8]) -> Option<usize> {
+    let programs = find_pat(buf)?;
+    return Some(
+        programs
+            .iter()
+            .filter(|(program_num, _)| *program_num != 0)
+            .count(),
+    );
+}
+
+// one program of a StreamDescription, pcr_pid being None when its PMT leaves the PCR PID as
+// 0x1FFF and streams (pid, stream_type, language) empty when no good PMT turned up
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProgramDescription {
+    pub program_num: u16,
+    pub pmt_pid: u16,
+    pub pmt_found: bool,
+    pub pcr_pid: Option<u16>,
+    pub streams: Vec<(u16, StreamType, Option<[u8; 3]>)>,
+}
+
+// the programs the PAT lists along with what their PMTs say they carry, Display giving a
+// tree to log
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StreamDescription {
+    pub programs: Vec<ProgramDescription>,
+}
+
+// reads the first PAT in buf and the first good PMT for each program listed (skipping the
+// network PID entry). No programs at all without a PAT
+pub fn describe_stream(buf: &[u8]) -> StreamDescription {
+    let mut description = StreamDescription::default();
+    let programs = match find_pat(buf) {
+        Some(programs) => programs,
+        None => return description,
+    };
+    for (program_num, pmt_pid) in programs.iter() {
+        if *program_num == 0 {
+            continue;
+        }
+        description.programs.push(ProgramDescription {
+            program_num: *program_num,
+            pmt_pid: *pmt_pid,
+            pmt_found: false,
+            pcr_pid: None,
+            streams: Vec::new(),
+        });
+    }
+    let pmt_pids: Vec<u16> = description.programs.iter().map(|p| p.pmt_pid).collect();
+    for (pid, section) in sections(buf, &pmt_pids) {
+        let section: &[u8] = &section;
+        // sections has already dropped anything failing its CRC
+        if section.len() < 16 || section.table_id() != 2 {
+            continue;
+        }
+        let pmt = section.table_data();
+        let program = description.programs.iter_mut().find(|program| {
+            program.pmt_pid == pid && program.program_num == section.table_id_ext()
+        });
+        let program = match program {
+            Some(program) if !program.pmt_found => program,
+            _ => continue,
+        };
+        program.pmt_found = true;
+        if pmt.has_pcr_pid() {
+            program.pcr_pid = Some(pmt.pcr_pid());
+        }
+        for (stream_type, es_pid, descs) in pmt.streams_with_descriptors() {
+            let language = descs.iter().find_map(|desc| desc.language());
+            program.streams.push((es_pid, stream_type, language));
+        }
+    }
+    return description;
+}
+
+impl fmt::Display for StreamDescription {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.programs.is_empty() {
+            return write!(f, "no programs");
+        }
+        for (n, program) in self.programs.iter().enumerate() {
+            if n > 0 {
+                writeln!(f)?;
+            }
+            write!(
+                f,
+                "Program {} (PMT 0x{:04X}",
+                program.program_num, program.pmt_pid
+            )?;
+            if !program.pmt_found {
+                write!(f, ", PMT not found)")?;
+                continue;
+            }
+            match program.pcr_pid {
+                Some(pcr_pid) => write!(f, ", PCR 0x{:04X})", pcr_pid)?,
+                None => write!(f, ", no PCR)")?,
+            }
+            for (pid, stream_type, language) in program.streams.iter() {
+                write!(f, "\n  Stream 0x{:04X}: {:?}", pid, stream_type)?;
+                if let Some(language) = language {
+                    write!(f, " ({})", String::from_utf8_lossy(language))?;
+                }
+            }
+        }
+        return Ok(());
+    }
+}
+
+// PCR PID of the first program listed in the PAT (skipping the network PID entry), read from
+// the first good PMT for it in buf. None without both
+pub fn detect_pcr_pid(buf: &[u8]) -> Option<u16> {
+    let (program_num, pmt_pid) = find_pat(buf)?
+        .into_iter()
+        .find(|(program_num, _)| *program_num != 0)?;
+    let mut assembler = SectionAssembler::new();
+    let mut scratch = vec![0u8; DEFAULT_MAX_SECTION_SIZE];
+    for window in buf.chunks_exact(188) {
+        let pkt = Packet::new(window.try_into().unwrap());
+        if pkt.sync() != 0x47 || pkt.pid() != pmt_pid {
+            continue;
+        }
+        for section in assembler.push(&pkt, &mut scratch) {
+            let section: &[u8] = &section;
+            // programs sharing a PMT PID each have their own section, told apart by
+            // program_number in table_id_extension
+            if section.len() >= 16
+                && section.table_id() == 2
+                && section.table_id_ext() == program_num
+                && calc_crc32(section) == section.crc32()
+            {
+                return Some(section.table_data().pcr_pid());
+            }
+        }
+    }
+    return None;
+}
+
+// for every PID whose section starts look like PSI, the table_ids found there. Only the
+// first section in each PUSI packet is looked at, and payloads opening with a PES start
+// code are left out
+pub fn table_ids_by_pid(buf: &[u8]) -> BTreeMap<u16, BTreeSet<u8>> {
+    let mut table_ids: BTreeMap<u16, BTreeSet<u8>> = BTreeMap::new();
+    for window in buf.chunks_exact(188) {
+        let pkt = Packet::new(window.try_into().unwrap());
+        if pkt.sync() != 0x47 || !pkt.is_section_start() {
+            continue;
+        }
+        let payload = pkt.payload_data();
+        if payload.len() < 4 || payload[0..3] == [0, 0, 1] {
+            continue;
+        }
+        let pointer = payload[0] as usize;
+        if 1 + pointer >= payload.len() || payload[1 + pointer] == 0xFF {
+            continue;
+        }
+        table_ids
+            .entry(pkt.pid())
+            .or_default()
+            .insert(payload[1 + pointer]);
+    }
+    return table_ids;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EitKind {
+    PresentFollowing,
+    OtherPresentFollowing,
+    Schedule,
+    OtherSchedule,
+    NotEit(u8),
+}
+
+// unlike PAT and PMT these read from the whole section since the kind comes from the
+// table_id, "other" tables describe a different transport stream
+pub trait EIT {
+    fn kind(&self) -> EitKind;
+    fn service_id(&self) -> u16;
+    fn transport_stream_id(&self) -> u16;
+    fn original_network_id(&self) -> u16;
+    fn segment_last_section_num(&self) -> u8;
+    fn last_table_id(&self) -> u8;
+}
+
+impl EIT for &[u8] {
+    fn kind(&self) -> EitKind {
+        return match self.table_id() {
+            0x4E => EitKind::PresentFollowing,
+            0x4F => EitKind::OtherPresentFollowing,
+            0x50..=0x5F => EitKind::Schedule,
+            0x60..=0x6F => EitKind::OtherSchedule,
+            other => EitKind::NotEit(other),
+        };
+    }
+    fn service_id(&self) -> u16 {
+        return self.table_id_ext();
+    }
+    fn transport_stream_id(&self) -> u16 {
+        return BigEndian::read_u16(&self[8..10]);
+    }
+    fn original_network_id(&self) -> u16 {
+        return BigEndian::read_u16(&self[10..12]);
+    }
+    fn segment_last_section_num(&self) -> u8 {
+        return self[12];
+    }
+    fn last_table_id(&self) -> u8 {
+        return self[13];
+    }
+}
+
+pub const EIT_PID: u16 = 0x0012;
+const SHORT_EVENT_TAG: u8 = 0x4D;
+
+// one event of an EIT with its short_event_descriptor. start_time is as coded on the wire,
+// 16 bit MJD then hhmmss in BCD, and duration hhmmss in BCD
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventSpec {
+    pub event_id: u16,
+    pub start_time: [u8; 5],
+    pub duration: [u8; 3],
+    pub running_status: u8,
+    pub language: [u8; 3],
+    pub name: Vec<u8>,
+    pub text: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum EitError {
+    // an event's short_event_descriptor, language, name and text together, came to more
+    // than its 8 bit length can hold
+    DescriptorTooLong { event_id: u16, len: usize },
+    // the events took the section past the 4096 bytes an EIT section may be
+    SectionTooLarge { size: usize, max: usize },
+}
+
+// splits a section over as many packets as it needs, the first with a zero pointer field
+// and the last padded out with 0xFF
+fn section_packets(pid: u16, section: &[u8], cc: u8) -> Vec<PacketData> {
+    let mut packets = Vec::new();
+    let mut rest = section;
+    let mut cc = cc;
+    loop {
+        let first = packets.is_empty();
+        let mut pkt = Packet::create_packet(false, first, false, pid, 0, 1, cc);
+        let start = if first { 5 } else { 4 };
+        if first {
+            pkt[4] = 0;
+        }
+        let take = rest.len().min(188 - start);
+        pkt[start..start + take].copy_from_slice(&rest[..take]);
+        rest = &rest[take..];
+        packets.push(pkt);
+        cc = (cc + 1) & 0xF;
+        if rest.is_empty() {
+            return packets;
+        }
+    }
+}
+
+// a present/following EIT for the actual transport stream as a single section, split over
+// packets on PID 0x0012 with cc counting up from the one given. Events too big for their
+// descriptor or the section are refused rather than written with wrapped lengths
+pub fn create_eit_packet(
+    service_id: u16,
+    tsid: u16,
+    onid: u16,
+    events: &[EventSpec],
+    cc: u8,
+) -> Result<Vec<PacketData>, EitError> {
+    let mut section = vec![0x4E, 0, 0];
+    section.extend_from_slice(&service_id.to_be_bytes());
+    section.extend_from_slice(&[0xC1, 0, 0]); // version 0 and current, section 0 of 0
+    section.extend_from_slice(&tsid.to_be_bytes());
+    section.extend_from_slice(&onid.to_be_bytes());
+    section.extend_from_slice(&[0, 0x4E]); // segment_last_section_number and last_table_id
+    for event in events.iter() {
+        let mut descriptor = vec![SHORT_EVENT_TAG, 0];
+        descriptor.extend_from_slice(&event.language);
+        descriptor.push(event.name.len() as u8);
+        descriptor.extend_from_slice(&event.name);
+        descriptor.push(event.text.len() as u8);
+        descriptor.extend_from_slice(&event.text);
+        if descriptor.len() - 2 > 255 {
+            return Err(EitError::DescriptorTooLong {
+                event_id: event.event_id,
+                len: descriptor.len() - 2,
+            });
+        }
+        descriptor[1] = (descriptor.len() - 2) as u8;
+
+        section.extend_from_slice(&event.event_id.to_be_bytes());
+        section.extend_from_slice(&event.start_time);
+        section.extend_from_slice(&event.duration);
+        // running_status, free_CA_mode left clear, then the descriptor loop length
+        let loop_len = ((event.running_status as u16 & 0x7) << 13) | descriptor.len() as u16;
+        section.extend_from_slice(&loop_len.to_be_bytes());
+        section.extend_from_slice(&descriptor);
+    }
+    // section_length counts the CRC as well as everything after the length field
+    let section_len = section.len() + 4 - 3;
+    if section_len + 3 > DEFAULT_MAX_SECTION_SIZE {
+        return Err(EitError::SectionTooLarge {
+            size: section_len + 3,
+            max: DEFAULT_MAX_SECTION_SIZE,
+        });
+    }
+    section[1] = 0xF0 | (section_len >> 8) as u8;
+    section[2] = section_len as u8;
+    section.extend_from_slice(&[0; 4]);
+    let crc = calc_crc32(&section);
+    let crc_idx = section.len() - 4;
+    BigEndian::write_u32(&mut section[crc_idx..], crc);
+    return Ok(section_packets(EIT_PID, &section, cc));
+}
+
+// events of a whole EIT section, name and text are taken from the first
+// short_event_descriptor of each and left empty when there isn't one
+pub fn parse_eit_events(section: &[u8]) -> Vec<EventSpec> {
+    let mut events = Vec::new();
+    if section.len() < 18 {
+        return events;
+    }
+    let data = section.table_data();
+    let mut pos = 6;
+    while pos + 12 <= data.len() {
+        let entry = &data[pos..];
+        let loop_len = (0xFFF & BigEndian::read_u16(&entry[10..12])) as usize;
+        if 12 + loop_len > entry.len() {
+            break;
+        }
+        let mut event = EventSpec {
+            event_id: BigEndian::read_u16(&entry[0..2]),
+            start_time: entry[2..7].try_into().unwrap(),
+            duration: entry[7..10].try_into().unwrap(),
+            running_status: entry[10] >> 5,
+            language: [0; 3],
+            name: Vec::new(),
+            text: Vec::new(),
+        };
+        let short_event = descriptors(&entry[12..12 + loop_len])
+            .find(|desc| desc.tag == SHORT_EVENT_TAG && desc.data.len() >= 4);
+        if let Some(desc) = short_event {
+            let name_end = (4 + desc.data[3] as usize).min(desc.data.len());
+            event.language.copy_from_slice(&desc.data[0..3]);
+            event.name = desc.data[4..name_end].to_vec();
+            if name_end < desc.data.len() {
+                let text_end = (name_end + 1 + desc.data[name_end] as usize).min(desc.data.len());
+                event.text = desc.data[name_end + 1..text_end].to_vec();
+            }
+        }
+        events.push(event);
+        pos += 12 + loop_len;
+    }
+    return events;
+}
+
+pub const SDT_BAT_PID: u16 = 0x0011;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SdtService {
+    pub service_id: u16,
+    pub eit_schedule: bool,
+    pub eit_present_following: bool,
+    pub running_status: u8,
+    pub free_ca_mode: bool,
+    pub descriptors: Vec<u8>,
+}
+
+// one SDT section, actual is false for an SDT describing another transport stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sdt {
+    pub actual: bool,
+    pub transport_stream_id: u16,
+    pub original_network_id: u16,
+    pub services: Vec<SdtService>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatTransport {
+    pub transport_stream_id: u16,
+    pub original_network_id: u16,
+    pub descriptors: Vec<u8>,
+}
+
+// one BAT section
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bat {
+    pub bouquet_id: u16,
+    pub descriptors: Vec<u8>,
+    pub transports: Vec<BatTransport>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SdtBatTable {
+    Sdt(Sdt),
+    Bat(Bat),
+}
+
+// takes a whole SDT section, None if it isn't one or its loop runs off the end
+pub fn parse_sdt(section: &[u8]) -> Option<Sdt> {
+    if section.len() < 15 || (section.table_id() != 0x42 && section.table_id() != 0x46) {
+        return None;
+    }
+    let data = section.table_data();
+    let mut services = Vec::new();
+    // original_network_id and a reserved byte come ahead of the service loop
+    let mut pos = 3;
+    while pos + 5 <= data.len() {
+        let entry = &data[pos..];
+        let desc_len = (0xFFF & BigEndian::read_u16(&entry[3..5])) as usize;
+        if 5 + desc_len > entry.len() {
+            return None;
+        }
+        services.push(SdtService {
+            service_id: BigEndian::read_u16(&entry[0..2]),
+            eit_schedule: (entry[2] & 0x2) != 0,
+            eit_present_following: (entry[2] & 0x1) != 0,
+            running_status: entry[3] >> 5,
+            free_ca_mode: (entry[3] & 0x10) != 0,
+            descriptors: entry[5..5 + desc_len].to_vec(),
+        });
+        pos += 5 + desc_len;
+    }
+    return Some(Sdt {
+        actual: section.table_id() == 0x42,
+        transport_stream_id: section.table_id_ext(),
+        original_network_id: BigEndian::read_u16(&data[0..2]),
+        services: services,
+    });
+}
+
+// takes a whole BAT section, None if it isn't one or a loop runs off the end
+pub fn parse_bat(section: &[u8]) -> Option<Bat> {
+    if section.len() < 16 || section.table_id() != 0x4A {
+        return None;
+    }
+    let data = section.table_data();
+    let desc_end = 2 + (0xFFF & BigEndian::read_u16(&data[0..2])) as usize;
+    if desc_end + 2 > data.len() {
+        return None;
+    }
+    let loop_end = desc_end + 2 + (0xFFF & BigEndian::read_u16(&data[desc_end..])) as usize;
+    if loop_end > data.len() {
+        return None;
+    }
+    let mut transports = Vec::new();
+    let mut pos = desc_end + 2;
+    while pos + 6 <= loop_end {
+        let entry = &data[pos..loop_end];
+        let desc_len = (0xFFF & BigEndian::read_u16(&entry[4..6])) as usize;
+        if 6 + desc_len > entry.len() {
+            return None;
+        }
+        transports.push(BatTransport {
+            transport_stream_id: BigEndian::read_u16(&entry[0..2]),
+            original_network_id: BigEndian::read_u16(&entry[2..4]),
+            descriptors: entry[6..6 + desc_len].to_vec(),
+        });
+        pos += 6 + desc_len;
+    }
+    return Some(Bat {
+        bouquet_id: section.table_id_ext(),
+        descriptors: data[2..desc_end].to_vec(),
+        transports: transports,
+    });
+}
+
+// reassembles sections off PID 0x0011 and hands each good one to the SDT or BAT parser by
+// its table_id, anything else sharing the PID (e.g. stuffing tables) is skipped
+pub struct SdtBatCollector {
+    assembler: SectionAssembler,
+    scratch: Vec<u8>,
+}
+
+impl Default for SdtBatCollector {
+    fn default() -> SdtBatCollector {
+        return SdtBatCollector::new();
+    }
+}
+
+impl SdtBatCollector {
+    pub fn new() -> SdtBatCollector {
+        return SdtBatCollector {
+            assembler: SectionAssembler::new(),
+            // SDT and BAT sections are at most 1024 bytes
+            scratch: vec![0; 1024],
+        };
+    }
+
+    pub fn push(&mut self, pkt: &Packet) -> Vec<SdtBatTable> {
+        let mut tables = Vec::new();
+        if pkt.pid() != SDT_BAT_PID {
+            return tables;
+        }
+        let _ = self.assembler.push_into(pkt, &mut self.scratch, |section| {
+            if section.len() < 12 || calc_crc32(section) != section.crc32() {
+                return;
+            }
+            let table = match section.table_id() {
+                0x42 | 0x46 => parse_sdt(section).map(SdtBatTable::Sdt),
+                0x4A => parse_bat(section).map(SdtBatTable::Bat),
+                _ => None,
+            };
+            tables.extend(table);
+        });
+        return tables;
+    }
+}
+
+// view of a user private section, table_id 0x40-0xFE, in either the short form or the long
+// form with a syntax section. Private sections may use the full 12 bit section_length
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PrivateSection<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> PrivateSection<'a> {
+    pub fn new(data: &'a [u8]) -> Option<PrivateSection<'a>> {
+        if data.len() < 3 || data[0] < 0x40 || data[0] == 0xFF {
+            return None;
+        }
+        let section_end = 3 + (0xFFF & BigEndian::read_u16(&data[1..3])) as usize;
+        let min_len = if (data[1] & 0x80) != 0 { 3 + 9 } else { 3 };
+        if section_end < min_len || data.len() < section_end {
+            return None;
+        }
+        return Some(PrivateSection {
+            data: &data[..section_end],
+        });
+    }
+
+    pub fn table_id(&self) -> u8 {
+        return self.data[0];
+    }
+    pub fn has_syntax_section(&self) -> bool {
+        return (self.data[1] & 0x80) != 0;
+    }
+    pub fn private_indicator(&self) -> bool {
+        return (self.data[1] & 0x40) != 0;
+    }
+    pub fn section_length(&self) -> u16 {
+        return 0xFFF & BigEndian::read_u16(&self.data[1..3]);
+    }
+    // everything after the header, less the syntax section and CRC when there is one
+    pub fn private_data(&self) -> &'a [u8] {
+        if self.has_syntax_section() {
+            return &self.data[8..self.data.len() - 4];
+        }
+        return &self.data[3..];
+    }
+}
+
+// what has been learned about which PIDs carry which tables, starting from the PAT
+#[derive(Default)]
+pub struct ProgramMap {
+    programs: BTreeMap<u16, u16>,
+    other_psi_pids: BTreeSet<u16>,
+}
+
+impl ProgramMap {
+    pub fn new() -> ProgramMap {
+        return ProgramMap {
+            programs: BTreeMap::new(),
+            other_psi_pids: BTreeSet::new(),
+        };
+    }
+
+    // takes the (program_number, pmt_pid) list as returned by find_pat
+    pub fn from_pat(programs: &[(u16, u16)]) -> ProgramMap {
+        let mut map = ProgramMap::new();
+        for (program_num, pmt_pid) in programs.iter() {
+            map.add_program(*program_num, *pmt_pid);
+        }
+        return map;
+    }
+
+    pub fn add_program(&mut self, program_num: u16, pmt_pid: u16) {
+        self.programs.insert(program_num, pmt_pid);
+    }
+
+    // for PIDs found carrying other tables, e.g. SDT/EIT/NIT
+    pub fn add_psi_pid(&mut self, pid: u16) {
+        self.other_psi_pids.insert(pid);
+    }
+
+    pub fn pmt_pid(&self, program_num: u16) -> Option<u16> {
+        return self.programs.get(&program_num).copied();
+    }
+
+    pub fn is_psi_pid(&self, pid: u16) -> bool {
+        return pid == 0
+            || self.other_psi_pids.contains(&pid)
+            || self.programs.values().any(|pmt_pid| *pmt_pid == pid);
+    }
+}
+
+// TR 101 290 PMT_error, every PMT PID in the map has to turn up at least once every
+// window_packets packets. Nothing is flagged until a whole window has gone by
+pub struct PmtPresenceChecker {
+    // pmt_pid to the index of the packet after the last one seen on it
+    last_seen: BTreeMap<u16, u64>,
+    window_packets: u64,
+    packets: u64,
+}
+
+impl PmtPresenceChecker {
+    pub fn new(map: &ProgramMap, window_packets: u64) -> PmtPresenceChecker {
+        return PmtPresenceChecker {
+            last_seen: map.programs.values().map(|pmt_pid| (*pmt_pid, 0)).collect(),
+            window_packets: window_packets.max(1),
+            packets: 0,
+        };
+    }
+
+    pub fn push(&mut self, pkt: &Packet) {
+        self.packets += 1;
+        if !pkt.has_payload() {
+            return;
+        }
+        if let Some(seen) = self.last_seen.get_mut(&pkt.pid()) {
+            *seen = self.packets;
+        }
+    }
+
+    // the PMT PIDs not seen in the last window_packets packets
+    pub fn missing(&self) -> Vec<u16> {
+        if self.packets < self.window_packets {
+            return Vec::new();
+        }
+        return self
+            .last_seen
+            .iter()
+            .filter(|(_, seen)| self.packets - **seen >= self.window_packets)
+            .map(|(pmt_pid, _)| *pmt_pid)
+            .collect();
+    }
+}
+
+// create_pmt_packet, refusing ES PIDs that would make the PMT invalid: the reserved
+// 0x0000-0x000F range, the null PID and any PID listed twice
+pub fn try_create_pmt_packet(
+    pid: u16,
+    pid_type_pairs: &[(u16, u8)],
+    cc: u8,
+) -> Result<PacketData, PmtError> {
+    let mut seen = BTreeSet::new();
+    for (es_pid, _) in pid_type_pairs.iter() {
+        if *es_pid <= 0x000F || *es_pid >= 0x1FFF {
+            return Err(PmtError::ReservedPid(*es_pid));
+        }
+        if !seen.insert(*es_pid) {
+            return Err(PmtError::DuplicatePid(*es_pid));
+        }
+    }
+    return Ok(create_pmt_packet(pid, pid_type_pairs, cc));
 }
 
 pub fn create_pmt_packet(pid: u16, pid_type_pairs: &[(u16, u8)], cc: u8) -> PacketData {
@@ -187,7 +1318,7 @@ pub fn create_pmt_packet(pid: u16, pid_type_pairs: &[(u16, u8)], cc: u8) -> Pack
         pmt[offset + 14 + pair_num] = (pair.0 & 0xFF) as u8; // low bits of ES pid
         pmt[offset + 15 + pair_num] = 0xF0; // reserved bits and zero program info
         pmt[offset + 16 + pair_num] = 0; // zero program info
-        pair_num = pair_num + 1;
+        pair_num = pair_num + 5; // each stream entry is 5 bytes
     }
 
     // the calc function drops the last 4 bytes when doing the checksum, so leaving them on here
@@ -200,12 +1331,217 @@ pub fn create_pmt_packet(pid: u16, pid_type_pairs: &[(u16, u8)], cc: u8) -> Pack
     return pmt;
 }
 
+// a PMT for program_num with a real PCR PID, which may well be one of the ES PIDs, plus
+// program descriptors and (pid, stream_type, es_info descriptors) for each stream. Split
+// over more than one packet if the descriptors need it
+pub fn create_pmt_packet_with_descriptors(
+    pid: u16,
+    program_num: u16,
+    pcr_pid: u16,
+    program_info: &[u8],
+    streams: &[(u16, u8, &[u8])],
+    cc: u8,
+) -> Vec<PacketData> {
+    let mut section = vec![2, 0, 0];
+    section.extend_from_slice(&program_num.to_be_bytes());
+    section.extend_from_slice(&[0xC1, 0, 0]); // version 0 and current, section 0 of 0
+    section.extend_from_slice(&(0xE000 | pcr_pid).to_be_bytes());
+    section.extend_from_slice(&[0, 0]);
+    set_program_info_len(&mut section, program_info.len() as u16);
+    section.extend_from_slice(program_info);
+    for (es_pid, stream_type, es_info) in streams.iter() {
+        let entry = section.len();
+        section.push(*stream_type);
+        section.extend_from_slice(&(0xE000 | es_pid).to_be_bytes());
+        section.extend_from_slice(&[0, 0]);
+        set_es_info_len(&mut section[entry..], es_info.len() as u16);
+        section.extend_from_slice(es_info);
+    }
+    let section_len = section.len() + 4 - 3;
+    section[1] = 0xB0 | (section_len >> 8) as u8;
+    section[2] = section_len as u8;
+    section.extend_from_slice(&[0; 4]);
+    let crc = calc_crc32(&section);
+    let crc_idx = section.len() - 4;
+    BigEndian::write_u32(&mut section[crc_idx..], crc);
+    return section_packets(pid, &section, cc);
+}
+
+// (ca_system_id, ca_pid) of one CA_descriptor
+pub type CaSpec = (u16, u16);
+
+// create_pmt_packet_with_descriptors for a scrambled program, each Some(CaSpec) giving a
+// CA_descriptor naming the ECM PID for the whole program or one stream
+pub fn create_pmt_packet_with_ca(
+    pid: u16,
+    program_num: u16,
+    pcr_pid: u16,
+    program_ca: Option<CaSpec>,
+    streams: &[(u16, u8, Option<CaSpec>)],
+    cc: u8,
+) -> Vec<PacketData> {
+    let to_descriptor = |ca: &Option<CaSpec>| match ca {
+        Some((ca_system_id, ca_pid)) => ca_descriptor(*ca_system_id, *ca_pid).to_vec(),
+        None => Vec::new(),
+    };
+    let program_info = to_descriptor(&program_ca);
+    let es_infos: Vec<Vec<u8>> = streams.iter().map(|(_, _, ca)| to_descriptor(ca)).collect();
+    let with_descriptors: Vec<(u16, u8, &[u8])> = streams
+        .iter()
+        .zip(es_infos.iter())
+        .map(|((es_pid, stream_type, _), es_info)| (*es_pid, *stream_type, &es_info[..]))
+        .collect();
+    return create_pmt_packet_with_descriptors(
+        pid,
+        program_num,
+        pcr_pid,
+        &program_info,
+        &with_descriptors,
+        cc,
+    );
+}
+
+pub const CAT_PID: u16 = 0x0001;
+
+// picks one program out of a multiplex: the PAT, its PMT and every PID the PMT refers to.
+// The PCR PID is often also the video PID, it is still only one PID to pass through
+pub struct ProgramExtractor {
+    pids: BTreeSet<u16>,
+    pmt_pid: u16,
+    // program_number to list alone in a rewritten PAT
+    pat_program: Option<u16>,
+}
+
+impl ProgramExtractor {
+    // pmt is the table data of the program's PMT section
+    pub fn from_pmt(pmt_pid: u16, pmt: &[u8]) -> ProgramExtractor {
+        let mut pids = BTreeSet::new();
+        pids.insert(0);
+        pids.insert(pmt_pid);
+        if pmt.has_pcr_pid() {
+            pids.insert(pmt.pcr_pid());
+        }
+        // ECM PIDs may be given for the whole program or per stream
+        if let Some(program_info) = pmt.descriptor_data() {
+            pids.extend(descriptors(program_info).filter_map(|desc| desc.ca_pid()));
+        }
+        for entry in stream_entries(pmt.elementary_streams()) {
+            pids.insert(entry.stream_pid());
+            pids.extend(descriptors(entry.es_info()).filter_map(|desc| desc.ca_pid()));
+        }
+        return ProgramExtractor {
+            pids: pids,
+            pmt_pid: pmt_pid,
+            pat_program: None,
+        };
+    }
+
+    // passes the CAT on PID 1 through along with the EMM PIDs its CA_descriptors name, cat
+    // being the table data of the CAT section
+    pub fn add_cat(&mut self, cat: &[u8]) {
+        self.pids.insert(CAT_PID);
+        self.pids
+            .extend(descriptors(cat).filter_map(|desc| desc.ca_pid()));
+    }
+
+    // replaces the PAT on the way out with one listing only this program as program_num,
+    // keeping the transport_stream_id of the original
+    pub fn rewrite_pat(&mut self, program_num: u16) {
+        self.pat_program = Some(program_num);
+    }
+
+    pub fn pids(&self) -> Vec<u16> {
+        return self.pids.iter().cloned().collect();
+    }
+
+    pub fn wants(&self, pid: u16) -> bool {
+        return self.pids.contains(&pid);
+    }
+
+    // the packets of buf belonging to the program. With rewrite_pat each PAT section start
+    // becomes a single program PAT and any continuation packets of the original are dropped
+    pub fn extract(&self, buf: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut pat_cc = 0;
+        for window in buf.chunks_exact(188) {
+            let pkt = Packet::new(window.try_into().unwrap());
+            if pkt.sync() != 0x47 || !self.wants(pkt.pid()) {
+                continue;
+            }
+            let program_num = match self.pat_program {
+                Some(program_num) if pkt.pid() == 0 => program_num,
+                _ => {
+                    out.extend_from_slice(window);
+                    continue;
+                }
+            };
+            let tables = match pkt.tables() {
+                Some(tables) if pkt.is_section_start() && tables.len() >= 5 => tables,
+                _ => continue,
+            };
+            let programs = [(program_num, self.pmt_pid)];
+            for pat in create_pat_packet_with_programs(tables.table_id_ext(), &programs, pat_cc) {
+                out.extend_from_slice(&pat);
+                pat_cc = (pat_cc + 1) & 0xF;
+            }
+        }
+        return out;
+    }
+}
+
 pub trait PMT {
     fn valid_pmt(&self) -> bool;
     fn pcr_pid(&self) -> u16;
+    fn has_pcr_pid(&self) -> bool;
     fn program_info_len(&self) -> u16;
     fn descriptor_data(&self) -> Option<&[u8]>;
     fn elementary_streams(&self) -> &[u8];
+    fn validate_pids(&self) -> Result<(), PmtError>;
+    fn streams_with_descriptors(
+        &self,
+    ) -> impl Iterator<Item = (StreamType, u16, Vec<Descriptor<'_>>)> + '_;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PmtError {
+    // ES PID in the 0x0000-0x000F reserved range or the 0x1FFF null PID
+    ReservedPid(u16),
+    DuplicatePid(u16),
+}
+
+// the ES loop of a whole PMT section (table_id onwards), cut off by section_length ahead of
+// the CRC so nothing after the loop, or the CRC itself, can be read as a stream entry.
+// Stuffing or a following section in the slice are left out the same way
+pub fn elementary_streams_bounded(section: &[u8]) -> &[u8] {
+    if section.len() < 3 {
+        return &[];
+    }
+    let end = (3 + section.section_length() as usize).min(section.len());
+    if end < 4 + 12 {
+        return &[];
+    }
+    let table_data = &section[8..end - 4];
+    let start = 4 + table_data.program_info_len() as usize;
+    if start > table_data.len() {
+        return &[];
+    }
+    return &table_data[start..];
+}
+
+// splits an ES loop into its entries, stopping at filler or an entry running off the end
+fn stream_entries(es: &[u8]) -> Vec<&[u8]> {
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    while pos + 5 <= es.len() {
+        let entry = &es[pos..];
+        let entry_len = 5 + entry.es_info_len() as usize;
+        if entry.stream_type() == 0xFF || entry_len > entry.len() {
+            break;
+        }
+        entries.push(&entry[..entry_len]);
+        pos += entry_len;
+    }
+    return entries;
 }
 
 impl PMT for &[u8] {
@@ -215,6 +1551,10 @@ impl PMT for &[u8] {
     fn pcr_pid(&self) -> u16 {
         return 0x1FFF & BigEndian::read_u16(&self[0..2]);
     }
+    // 0x1FFF in PCR_PID means no PCR is carried for the program
+    fn has_pcr_pid(&self) -> bool {
+        return self.pcr_pid() != 0x1FFF;
+    }
     fn program_info_len(&self) -> u16 {
         return 0x3FF & BigEndian::read_u16(&self[2..4]);
     }
@@ -229,11 +1569,381 @@ impl PMT for &[u8] {
         let desc_len = self.program_info_len() as usize;
         return &self[4 + desc_len..];
     }
+    fn validate_pids(&self) -> Result<(), PmtError> {
+        let desc_len = self.program_info_len() as usize;
+        let mut seen = BTreeSet::new();
+        for entry in stream_entries(&self[4 + desc_len..]) {
+            let pid = entry.stream_pid();
+            if pid <= 0x000F || pid == 0x1FFF {
+                return Err(PmtError::ReservedPid(pid));
+            }
+            if !seen.insert(pid) {
+                return Err(PmtError::DuplicatePid(pid));
+            }
+        }
+        return Ok(());
+    }
+    // everything needed to set up a track per stream: its type (resolved through any
+    // registration descriptor), PID and descriptors
+    fn streams_with_descriptors(
+        &self,
+    ) -> impl Iterator<Item = (StreamType, u16, Vec<Descriptor<'_>>)> + '_ {
+        return stream_entries(self.elementary_streams())
+            .into_iter()
+            .map(|entry| {
+                let es_info: &[u8] = &entry[5..];
+                return (
+                    entry.stream_type_enum(),
+                    entry.stream_pid(),
+                    descriptors(es_info).collect(),
+                );
+            });
+    }
+}
+
+// edits the common syntax fields of any long form section in place, leaving every other
+// byte as it was, and recomputes the CRC when finished
+pub struct SectionEditor {
+    section: Vec<u8>,
+}
+
+impl SectionEditor {
+    // None unless this is a whole long form section
+    pub fn new(section: &[u8]) -> Option<SectionEditor> {
+        if section.len() < 12
+            || !section.has_syntax_section()
+            || !section.is_complete(section.section_length())
+        {
+            return None;
+        }
+        return Some(SectionEditor {
+            section: section.section_data().to_vec(),
+        });
+    }
+
+    pub fn set_version(&mut self, version: u8) -> &mut SectionEditor {
+        self.section[5] = (self.section[5] & 0xC1) | ((version & 0x1F) << 1);
+        return self;
+    }
+
+    pub fn set_current(&mut self, current: bool) -> &mut SectionEditor {
+        self.section[5] = (self.section[5] & 0xFE) | current as u8;
+        return self;
+    }
+
+    pub fn set_table_id_ext(&mut self, table_id_ext: u16) -> &mut SectionEditor {
+        BigEndian::write_u16(&mut self.section[3..5], table_id_ext);
+        return self;
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        let mut section = self.section;
+        let crc = calc_crc32(&section);
+        let crc_idx = section.len() - 4;
+        BigEndian::write_u32(&mut section[crc_idx..], crc);
+        return section;
+    }
+}
+
+// stamps the version_number on successive emissions of one PAT/PMT, bumping it only when the
+// content has changed since the last one so decoders aren't made to reconfigure for nothing.
+// The first section emitted keeps the version it was built with
+#[derive(Default)]
+pub struct TableUpdater {
+    last: Option<Vec<u8>>,
+}
+
+impl TableUpdater {
+    pub fn new() -> TableUpdater {
+        return TableUpdater { last: None };
+    }
+
+    // the section to send out in place of section, None if it is not a whole long form section
+    pub fn update(&mut self, section: &[u8]) -> Option<Vec<u8>> {
+        let mut editor = SectionEditor::new(section)?;
+        if let Some(last) = &self.last {
+            let last: &[u8] = last;
+            let version = if same_content(last, section) {
+                last.version()
+            } else {
+                (last.version() + 1) & 0x1F
+            };
+            editor.set_version(version);
+        }
+        let emitted = editor.finish();
+        self.last = Some(emitted.clone());
+        return Some(emitted);
+    }
+
+    // version_number of the last section emitted
+    pub fn version(&self) -> Option<u8> {
+        let last: &[u8] = self.last.as_ref()?;
+        return Some(last.version());
+    }
+}
+
+// PMTs go through pmt_content_eq, anything else has to match byte for byte past the version
+fn same_content(a: &[u8], b: &[u8]) -> bool {
+    let b = b.section_data();
+    if a.table_id() != b.table_id() {
+        return false;
+    }
+    if a.table_id() == 2 {
+        return pmt_content_eq(a, b);
+    }
+    return a.table_id_ext() == b.table_id_ext()
+        && a.current() == b.current()
+        && a.section_num() == b.section_num()
+        && a.last_section_num() == b.last_section_num()
+        && a.table_data() == b.table_data();
+}
+
+// drops elementary streams from a PMT and packs the result back into a single packet
+pub struct PmtEditor {
+    // table_id through the end of the program info
+    header: Vec<u8>,
+    streams: Vec<Vec<u8>>,
+}
+
+impl PmtEditor {
+    // None unless this is a whole PMT section with its program info in bounds
+    pub fn new(section: &[u8]) -> Option<PmtEditor> {
+        if section.len() < 16
+            || section.table_id() != 2
+            || !section.is_complete(section.section_length())
+        {
+            return None;
+        }
+        let section = section.section_data();
+        let pmt = section.table_data();
+        let header_len = 12 + pmt.program_info_len() as usize;
+        if header_len > section.len() - 4 {
+            return None;
+        }
+        return Some(PmtEditor {
+            header: section[..header_len].to_vec(),
+            streams: stream_entries(pmt.elementary_streams())
+                .iter()
+                .map(|entry| entry.to_vec())
+                .collect(),
+        });
+    }
+
+    pub fn remove_stream(&mut self, es_pid: u16) -> &mut PmtEditor {
+        self.streams.retain(|entry| {
+            let entry: &[u8] = entry;
+            return entry.stream_pid() != es_pid;
+        });
+        return self;
+    }
+
+    // the edited section with its section_length and CRC recomputed
+    pub fn section(&self) -> Vec<u8> {
+        let mut section = self.header.clone();
+        for entry in self.streams.iter() {
+            section.extend_from_slice(entry);
+        }
+        let section_len = section.len() + 4 - 3;
+        section[1] = (section[1] & 0xF0) | (section_len >> 8) as u8;
+        section[2] = section_len as u8;
+        section.extend_from_slice(&[0; 4]);
+        let crc = calc_crc32(&section);
+        let crc_idx = section.len() - 4;
+        BigEndian::write_u32(&mut section[crc_idx..], crc);
+        return section;
+    }
+
+    // laid out the same way as create_pmt_packet, the section at the end of the packet and the
+    // pointer field skipping the 0xFF filler ahead of it. A section that no longer fits in the
+    // 183 bytes after the pointer field is TooLarge
+    pub fn build(&self, pid: u16, cc: u8) -> Result<PacketData, PacketError> {
+        let section = self.section();
+        if section.len() > 183 {
+            return Err(PacketError::TooLarge(section.len() + 1));
+        }
+        let mut pmt = Packet::create_packet(false, true, false, pid, 0, 1, cc);
+        let pointer = 183 - section.len();
+        pmt[4] = pointer as u8;
+        pmt[5 + pointer..].copy_from_slice(&section);
+        return Ok(pmt);
+    }
+}
+
+// rewrite the 12 bit length fields of a PMT in place. The 4 reserved bits above them are
+// set, and the two unused high bits of the length left 0 so the value stays within 10 bits
+pub fn set_program_info_len(section: &mut [u8], len: u16) {
+    section[10] = 0xF0 | ((len >> 8) & 0x03) as u8;
+    section[11] = len as u8;
+}
+
+pub fn set_es_info_len(es: &mut [u8], len: u16) {
+    es[3] = 0xF0 | ((len >> 8) & 0x03) as u8;
+    es[4] = len as u8;
+}
+
+// compares two PMT sections on what a demuxer cares about: PCR PID, program descriptors and
+// each stream's type, PID and descriptors. version_number, section_number and the CRC are
+// ignored, as are reserved bits
+pub fn pmt_content_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() < 16 || b.len() < 16 || a.table_id_ext() != b.table_id_ext() {
+        return false;
+    }
+    let a = a.table_data();
+    let b = b.table_data();
+    // a program_info_length running past the section can't be compared, so it's a mismatch
+    let (a_es, b_es) = match (
+        a.get(4 + a.program_info_len() as usize..),
+        b.get(4 + b.program_info_len() as usize..),
+    ) {
+        (Some(a_es), Some(b_es)) => (a_es, b_es),
+        _ => return false,
+    };
+    if a.pcr_pid() != b.pcr_pid() || a.descriptor_data() != b.descriptor_data() {
+        return false;
+    }
+    let a_streams = stream_entries(a_es);
+    let b_streams = stream_entries(b_es);
+    return a_streams.len() == b_streams.len()
+        && a_streams.iter().zip(b_streams.iter()).all(|(a, b)| {
+            a.stream_type() == b.stream_type()
+                && a.stream_pid() == b.stream_pid()
+                && a.es_info() == b.es_info()
+        });
+}
+
+// recomputes what section_length should be from the structure of a PAT or PMT and checks
+// the declared one against it: the PAT loop has to be whole 4 byte entries, the PMT's
+// program info and ES entries (with their ES info) have to end exactly where the CRC
+// starts. Other tables only get checked for fitting inside section
+pub fn verify_section_length(section: &[u8]) -> bool {
+    if section.len() < 3 {
+        return false;
+    }
+    let declared = 3 + section.section_length() as usize;
+    if declared > section.len() {
+        return false;
+    }
+    match section.table_id() {
+        0 => return declared >= 12 && (declared - 12).is_multiple_of(4),
+        2 => {
+            if declared < 16 {
+                return false;
+            }
+            let loop_end = declared - 4;
+            let mut pos = 12 + (&section[8..]).program_info_len() as usize;
+            while pos < loop_end {
+                if pos + 5 > loop_end {
+                    return false;
+                }
+                pos += 5 + (&section[pos..]).es_info_len() as usize;
+            }
+            return pos == loop_end;
+        }
+        _ => return true,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StreamType {
+    Mpeg1Video,
+    Mpeg2Video,
+    Mpeg1Audio,
+    Mpeg2Audio,
+    PrivateSections,
+    // 0x06 with no registration descriptor saying what's inside
+    PrivatePes,
+    AdtsAac,
+    Mpeg4Video,
+    LatmAac,
+    H264,
+    H265,
+    // ATSC A/52, 0x81 or 0x06 registered as "AC-3"
+    Ac3,
+    // 0x87 or 0x06 registered as "EAC3"
+    EAc3,
+    Scte35,
+    // 0x06 registered as "KLVA"
+    Klva,
+    // 0xEA or 0x06 registered as "VC-1"
+    Vc1,
+    // SMPTE 302M audio, 0x06 registered as "BSSD"
+    Smpte302m,
+    Unknown(u8),
+}
+
+impl StreamType {
+    pub fn from_type(stream_type: u8) -> StreamType {
+        return match stream_type {
+            0x01 => StreamType::Mpeg1Video,
+            0x02 => StreamType::Mpeg2Video,
+            0x03 => StreamType::Mpeg1Audio,
+            0x04 => StreamType::Mpeg2Audio,
+            0x05 => StreamType::PrivateSections,
+            0x06 => StreamType::PrivatePes,
+            0x0F => StreamType::AdtsAac,
+            0x10 => StreamType::Mpeg4Video,
+            0x11 => StreamType::LatmAac,
+            0x1B => StreamType::H264,
+            0x24 => StreamType::H265,
+            0x81 => StreamType::Ac3,
+            0x86 => StreamType::Scte35,
+            0x87 => StreamType::EAc3,
+            0xEA => StreamType::Vc1,
+            other => StreamType::Unknown(other),
+        };
+    }
+
+    pub fn from_registration(format_identifier: &[u8]) -> Option<StreamType> {
+        return match format_identifier {
+            b"AC-3" => Some(StreamType::Ac3),
+            b"EAC3" => Some(StreamType::EAc3),
+            b"KLVA" => Some(StreamType::Klva),
+            b"VC-1" => Some(StreamType::Vc1),
+            b"BSSD" => Some(StreamType::Smpte302m),
+            b"HEVC" => Some(StreamType::H265),
+            _ => None,
+        };
+    }
+}
+
+// what a stream_type 0x06 stream actually carries, going by its ES descriptors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PrivateStreamKind {
+    Teletext,
+    // DVB subtitles
+    Subtitle,
+    // DVB AC-3_descriptor
+    Ac3,
+    // a registration descriptor with a format_identifier StreamType knows
+    Registered(StreamType),
+    // no descriptor saying, or not a 0x06 stream at all
+    Unknown,
+}
+
+// resolves a PMT elementary stream entry of type 0x06 from the first of its descriptors that
+// identifies the content
+pub fn resolve_private_stream(es: &[u8]) -> PrivateStreamKind {
+    if es.len() < 5 || es.stream_type() != 0x06 || es.len() < 5 + es.es_info_len() as usize {
+        return PrivateStreamKind::Unknown;
+    }
+    for desc in descriptors(es.es_info()) {
+        match desc.tag {
+            TELETEXT_TAG => return PrivateStreamKind::Teletext,
+            SUBTITLING_TAG => return PrivateStreamKind::Subtitle,
+            AC3_TAG => return PrivateStreamKind::Ac3,
+            _ => {}
+        }
+        if let Some(resolved) = desc.registration().and_then(StreamType::from_registration) {
+            return PrivateStreamKind::Registered(resolved);
+        }
+    }
+    return PrivateStreamKind::Unknown;
 }
 
 pub trait ElementaryStream {
     fn valid_stream(&self) -> bool;
     fn stream_type(&self) -> u8;
+    fn stream_type_enum(&self) -> StreamType;
     fn stream_pid(&self) -> u16;
     fn es_info_len(&self) -> u16;
     fn es_info(&self) -> &[u8];
@@ -248,6 +1958,19 @@ impl ElementaryStream for &[u8] {
     fn stream_type(&self) -> u8 {
         return self[0];
     }
+    fn stream_type_enum(&self) -> StreamType {
+        let stream_type = StreamType::from_type(self.stream_type());
+        if stream_type == StreamType::PrivatePes {
+            // private PES is only identifiable by its registration descriptor
+            for desc in descriptors(self.es_info()) {
+                if let Some(resolved) = desc.registration().and_then(StreamType::from_registration)
+                {
+                    return resolved;
+                }
+            }
+        }
+        return stream_type;
+    }
     fn stream_pid(&self) -> u16 {
         return 0x1FFF & BigEndian::read_u16(&self[1..3]);
     }
@@ -269,7 +1992,13 @@ impl ElementaryStream for &[u8] {
     }
 }
 
+// returns 0 for sections too short to hold a CRC, use try_calc_crc32 to tell that apart
+// from a CRC that really is 0
 pub fn calc_crc32(section_data: &[u8]) -> u32 {
+    return try_calc_crc32(section_data).unwrap_or(0);
+}
+
+pub fn try_calc_crc32(section_data: &[u8]) -> Option<u32> {
     let section_len = section_data.len();
     if section_len >= 4 {
         let mut crc32 = 0xffffffff;
@@ -288,7 +2017,7 @@ pub fn calc_crc32(section_data: &[u8]) -> u32 {
             byte_pos = byte_pos + 1;
         }
         //println!("crc for {} was {}", hex::encode_upper(to_sum), crc32);
-        return crc32;
+        return Some(crc32);
     }
-    return 0;
+    return None;
 }
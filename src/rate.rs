@@ -0,0 +1,171 @@
+use crate::packet::{AdaptationField, Packet, PacketHeader};
+use crate::time::PCR_WRAP;
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+
+// planning helpers for muxer configuration, all bitrates are in bits per second
+
+const PACKET_BITS: u64 = 188 * 8;
+
+pub fn packets_per_second(bitrate_bps: u64) -> u64 {
+    return bitrate_bps / PACKET_BITS;
+}
+
+// lowest bitrate still able to fit one PCR carrying packet into every interval
+pub fn min_bitrate_for_pcr(pcr_interval_ms: u32) -> u64 {
+    let interval_ms = pcr_interval_ms.max(1) as u64;
+    return (PACKET_BITS * 1000).div_ceil(interval_ms);
+}
+
+// how many PCR carrying packets go out each second, capped by how many packets the
+// bitrate allows at all
+pub fn pcr_packets_per_second(bitrate_bps: u64, interval_ms: u32) -> u64 {
+    let interval_ms = interval_ms.max(1) as u64;
+    let wanted = 1000u64.div_ceil(interval_ms);
+    return wanted.min(packets_per_second(bitrate_bps));
+}
+
+// the packet spacing between PCRs that keeps within the interval at this bitrate
+pub fn packets_between_pcrs(bitrate_bps: u64, interval_ms: u32) -> u64 {
+    return (bitrate_bps * interval_ms as u64 / (PACKET_BITS * 1000)).max(1);
+}
+
+// average bitrate over a whole capture from the packet count between the first and last
+// PCR on pcr_pid, None without two distinct PCRs to measure between
+pub fn nominal_bitrate(buf: &[u8], pcr_pid: u16) -> Option<f64> {
+    let mut first: Option<(usize, u64)> = None;
+    let mut last: Option<(usize, u64)> = None;
+    for (index, window) in buf.chunks_exact(188).enumerate() {
+        let pkt = Packet::new(window.try_into().unwrap());
+        if pkt.sync() != 0x47 || pkt.pid() != pcr_pid || !pkt.has_pcr() {
+            continue;
+        }
+        if first.is_none() {
+            first = Some((index, pkt.pcr()));
+        }
+        last = Some((index, pkt.pcr()));
+    }
+    let ((first_index, first_pcr), (last_index, last_pcr)) = (first?, last?);
+    let ticks = (last_pcr + PCR_WRAP - first_pcr) % PCR_WRAP;
+    if ticks == 0 {
+        return None;
+    }
+    let bits = ((last_index - first_index) as u64 * PACKET_BITS) as f64;
+    return Some(bits * 27_000_000.0 / ticks as f64);
+}
+
+// for CBR output: which packet indices should carry a PCR to keep within interval_ms, and
+// the (base, extension) each should hold, the time the packet starts going out at bitrate
+// counting from a PCR of 0 on packet 0
+pub struct PcrScheduler {
+    bitrate_bps: u64,
+    spacing: u64,
+}
+
+impl PcrScheduler {
+    pub fn new(bitrate_bps: u64, interval_ms: u32) -> PcrScheduler {
+        return PcrScheduler {
+            bitrate_bps: bitrate_bps.max(1),
+            spacing: packets_between_pcrs(bitrate_bps, interval_ms),
+        };
+    }
+
+    // packets from one PCR to the next
+    pub fn spacing(&self) -> u64 {
+        return self.spacing;
+    }
+
+    pub fn pcr_for_packet(&self, index: u64) -> Option<(u64, u16)> {
+        if !index.is_multiple_of(self.spacing) {
+            return None;
+        }
+        let ticks = index as u128 * PACKET_BITS as u128 * 27_000_000 / self.bitrate_bps as u128;
+        let pcr = (ticks % PCR_WRAP as u128) as u64;
+        return Some((pcr / 300, (pcr % 300) as u16));
+    }
+}
+
+// measures the transport stream bitrate from the packets counted between PCRs on one PID.
+// A PCR flagged as a discontinuity starts a new timebase, so the measurement starts over
+// from it rather than diffing across the jump. Each PCR is diffed against the one before so
+// the PCR wrapping only has to be allowed for a step at a time, the steps adding up to the
+// measurement over the whole timebase
+pub struct BitrateEstimator {
+    pcr_pid: u16,
+    packets: u64,
+    // PCR and packet count at the last PCR
+    baseline: Option<(u64, u64)>,
+    // bits and 27MHz ticks since the timebase started
+    total_bits: u64,
+    total_ticks: u64,
+    bitrate: Option<u64>,
+}
+
+impl BitrateEstimator {
+    pub fn new(pcr_pid: u16) -> BitrateEstimator {
+        return BitrateEstimator {
+            pcr_pid: pcr_pid,
+            packets: 0,
+            baseline: None,
+            total_bits: 0,
+            total_ticks: 0,
+            bitrate: None,
+        };
+    }
+
+    pub fn push(&mut self, pkt: &Packet) {
+        let packets = self.packets;
+        self.packets += 1;
+        if pkt.pid() != self.pcr_pid || !pkt.has_pcr() {
+            return;
+        }
+        let pcr = pkt.pcr();
+        match self.baseline {
+            Some((last_pcr, last_packets)) if !pkt.is_discontinuity() => {
+                self.total_ticks += (pcr + PCR_WRAP - last_pcr) % PCR_WRAP;
+                self.total_bits += (packets - last_packets) * PACKET_BITS;
+                let bits = self.total_bits as u128 * 27_000_000;
+                if let Some(bitrate) = bits.checked_div(self.total_ticks as u128) {
+                    self.bitrate = Some(bitrate as u64);
+                }
+            }
+            _ => {
+                self.total_bits = 0;
+                self.total_ticks = 0;
+                self.bitrate = None;
+            }
+        }
+        self.baseline = Some((pcr, packets));
+    }
+
+    // None until two PCRs of the same timebase have been seen
+    pub fn bitrate(&self) -> Option<u64> {
+        return self.bitrate;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PidStats {
+    pub packets: u64,
+    pub payload_bytes: u64,
+    // mean Packet::payload_efficiency over the PID's packets
+    pub avg_efficiency: f32,
+}
+
+// packet and payload totals per PID across a capture, windows off the sync byte are skipped
+pub fn pid_stats(buf: &[u8]) -> BTreeMap<u16, PidStats> {
+    let mut stats: BTreeMap<u16, PidStats> = BTreeMap::new();
+    for window in buf.chunks_exact(188) {
+        let pkt = Packet::new(window.try_into().unwrap());
+        if pkt.sync() != 0x47 {
+            continue;
+        }
+        let entry = stats.entry(pkt.pid()).or_default();
+        entry.packets += 1;
+        entry.payload_bytes += pkt.payload_len() as u64;
+    }
+    for entry in stats.values_mut() {
+        entry.avg_efficiency = entry.payload_bytes as f32 / (entry.packets * 184) as f32;
+    }
+    return stats;
+}
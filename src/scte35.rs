@@ -0,0 +1,110 @@
+use crate::psi::TableHeader;
+use byteorder::{BigEndian, ByteOrder};
+
+pub const SPLICE_INFO_TABLE_ID: u8 = 0xFC;
+pub const SPLICE_INSERT: u8 = 0x05;
+
+// the splice_insert command of a splice_info_section. pts_time is None for an immediate
+// splice, and also for component splices where each component carries its own time.
+// duration is None without a break_duration
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpliceInsert {
+    pub pts_adjustment: u64,
+    pub splice_event_id: u32,
+    pub cancel: bool,
+    pub out_of_network: bool,
+    pub immediate: bool,
+    pub pts_time: Option<u64>,
+    pub auto_return: bool,
+    pub duration: Option<u64>,
+    pub unique_program_id: u16,
+    pub avail_num: u8,
+    pub avails_expected: u8,
+}
+
+fn read_33_bits(buf: &[u8]) -> u64 {
+    return ((buf[0] as u64 & 0x1) << 32) | BigEndian::read_u32(&buf[1..5]) as u64;
+}
+
+// splice_time(), returning the pts_time if time_specified_flag is set and the bytes used
+fn splice_time(buf: &[u8]) -> Option<(Option<u64>, usize)> {
+    if buf.is_empty() {
+        return None;
+    }
+    if buf[0] & 0x80 == 0 {
+        return Some((None, 1));
+    }
+    if buf.len() < 5 {
+        return None;
+    }
+    return Some((Some(read_33_bits(buf)), 5));
+}
+
+// None unless the section is a splice_info_section carrying a splice_insert that fits
+pub fn parse_splice_insert(section: &[u8]) -> Option<SpliceInsert> {
+    if section.len() < 14 || section.table_id() != SPLICE_INFO_TABLE_ID {
+        return None;
+    }
+    let section_end = 3 + (0xFFF & BigEndian::read_u16(&section[1..3])) as usize;
+    if section.len() < section_end || section[13] != SPLICE_INSERT {
+        return None;
+    }
+    let command = &section[14..section_end];
+    if command.len() < 5 {
+        return None;
+    }
+    let mut insert = SpliceInsert {
+        pts_adjustment: read_33_bits(&section[4..9]),
+        splice_event_id: BigEndian::read_u32(&command[0..4]),
+        cancel: command[4] & 0x80 != 0,
+        out_of_network: false,
+        immediate: false,
+        pts_time: None,
+        auto_return: false,
+        duration: None,
+        unique_program_id: 0,
+        avail_num: 0,
+        avails_expected: 0,
+    };
+    if insert.cancel {
+        return Some(insert);
+    }
+    if command.len() < 6 {
+        return None;
+    }
+    let flags = command[5];
+    insert.out_of_network = flags & 0x80 != 0;
+    let program_splice = flags & 0x40 != 0;
+    let has_duration = flags & 0x20 != 0;
+    insert.immediate = flags & 0x10 != 0;
+    let mut pos = 6;
+    if program_splice {
+        if !insert.immediate {
+            let (pts_time, used) = splice_time(&command[pos..])?;
+            insert.pts_time = pts_time;
+            pos += used;
+        }
+    } else {
+        let component_count = *command.get(pos)? as usize;
+        pos += 1;
+        for _ in 0..component_count {
+            // component_tag then, unless immediate, its own splice_time
+            pos += 1;
+            if !insert.immediate {
+                let (_, used) = splice_time(command.get(pos..)?)?;
+                pos += used;
+            }
+        }
+    }
+    if has_duration {
+        let break_duration = command.get(pos..pos + 5)?;
+        insert.auto_return = break_duration[0] & 0x80 != 0;
+        insert.duration = Some(read_33_bits(break_duration));
+        pos += 5;
+    }
+    let tail = command.get(pos..pos + 4)?;
+    insert.unique_program_id = BigEndian::read_u16(&tail[0..2]);
+    insert.avail_num = tail[2];
+    insert.avails_expected = tail[3];
+    return Some(insert);
+}
@@ -0,0 +1,69 @@
+use crate::packet::{HeaderFields, Packet, PacketData};
+use crate::pes::write_timestamp;
+use crate::psi::{create_pat_packet_with_programs, create_pmt_packet_with_descriptors};
+
+// a whole capture, packets back to back
+pub type TsStream = Vec<u8>;
+
+// (program_number, pmt_pid, video_pid, audio_pid) of the programs in create_two_program_stream,
+// video carrying the PCR in each
+pub const TWO_PROGRAMS: [(u16, u16, u16, u16); 2] =
+    [(1, 0x1000, 0x100, 0x101), (2, 0x1100, 0x200, 0x201)];
+
+// one packet holding a whole PES with a PTS, on video PIDs carrying pcr as well
+fn pes_packet(pid: u16, stream_id: u8, pts: u64, pcr: Option<u64>, cc: u8) -> PacketData {
+    let header = HeaderFields {
+        tei: false,
+        pusi: true,
+        priority: false,
+        pid: pid,
+        tsc: 0,
+        afc: 3,
+        cc: cc,
+    };
+    let mut pes = vec![0, 0, 1, stream_id, 0, 0, 0x80, 0x80, 5, 0x20, 0, 0, 0, 0];
+    write_timestamp(&mut pes[9..14], pts);
+    pes.resize(170, 0xAA);
+    let pes_len = (pes.len() - 6) as u16;
+    pes[4..6].copy_from_slice(&pes_len.to_be_bytes());
+    let af: &[u8] = match pcr {
+        Some(_) => &[0x10, 0, 0, 0, 0, 0x7E, 0],
+        None => &[0],
+    };
+    let data = Packet::create_packet_full(header, Some(af), &pes).unwrap();
+    return match pcr {
+        Some(pcr) => Packet::with_pcr(data, pcr),
+        None => data,
+    };
+}
+
+// PAT listing the two TWO_PROGRAMS, their PMTs each with an H.264 video and ADTS AAC audio
+// stream, then a few rounds of one PES packet per stream with cc counting up per PID and a
+// PCR on every video packet 40ms apart
+pub fn create_two_program_stream() -> TsStream {
+    let mut stream = Vec::new();
+    let programs: Vec<(u16, u16)> = TWO_PROGRAMS
+        .iter()
+        .map(|(program_num, pmt_pid, _, _)| (*program_num, *pmt_pid))
+        .collect();
+    for pkt in create_pat_packet_with_programs(1, &programs, 0) {
+        stream.extend_from_slice(&pkt);
+    }
+    for (program_num, pmt_pid, video_pid, audio_pid) in TWO_PROGRAMS.iter() {
+        let streams: [(u16, u8, &[u8]); 2] = [(*video_pid, 0x1B, &[]), (*audio_pid, 0x0F, &[])];
+        for pkt in
+            create_pmt_packet_with_descriptors(*pmt_pid, *program_num, *video_pid, &[], &streams, 0)
+        {
+            stream.extend_from_slice(&pkt);
+        }
+    }
+    for round in 0..4u64 {
+        let pts = 90_000 + round * 3_600;
+        let cc = round as u8 & 0xF;
+        for (_, _, video_pid, audio_pid) in TWO_PROGRAMS.iter() {
+            stream.extend_from_slice(&pes_packet(*video_pid, 0xE0, pts, Some(pts * 300), cc));
+            stream.extend_from_slice(&pes_packet(*audio_pid, 0xC0, pts, None, cc));
+        }
+    }
+    return stream;
+}
@@ -0,0 +1,406 @@
+use crate::packet::{pcr_to_nanos, AdaptationField, Packet, PacketData, PacketHeader, Payload};
+use crate::pes::{write_timestamp, PesHeader};
+use std::collections::VecDeque;
+use std::convert::TryInto;
+
+// M2TS arrival timestamps are a 30 bit count of the 27MHz clock
+const ARRIVAL_TIMESTAMP_MASK: u32 = 0x3FFF_FFFF;
+
+// ticks between two arrival timestamps, allowing for the 30 bit counter wrapping
+pub fn arrival_delta(earlier: u32, later: u32) -> u32 {
+    return later.wrapping_sub(earlier) & ARRIVAL_TIMESTAMP_MASK;
+}
+
+// how far the arrival spacing of two PCR carrying packets strayed from their PCR spacing
+// in 27MHz ticks, positive when the second packet arrived early. Either clock may wrap
+// between the two
+pub fn arrival_jitter(earlier: (u32, u64), later: (u32, u64)) -> i64 {
+    let pcr_delta = pcr_diff(earlier.1, later.1);
+    let arrival = arrival_delta(earlier.0, later.0) as i64;
+    return pcr_delta - arrival;
+}
+
+// PCR wraps along with its 33 bit 90kHz base
+pub const PCR_WRAP: u64 = (1 << 33) * 300;
+pub const PTS_WRAP: u64 = 1 << 33;
+
+// the 90kHz clock a PCR's base counts, dropping the 27MHz extension. PCR_WRAP is exactly
+// PTS_WRAP * 300 so both clocks wrap at the same instant and a PCR below PCR_WRAP lands
+// below PTS_WRAP, letting a PTS and PCR from either side of the wrap go through pcr_diff
+pub fn pts_from_pcr(pcr: u64) -> u64 {
+    return pcr / 300;
+}
+
+// a 90kHz PTS as a PCR with a zero extension, a PTS below PTS_WRAP lands below PCR_WRAP
+pub fn pcr_from_pts(pts: u64) -> u64 {
+    return pts * 300;
+}
+
+// b - a in 27MHz ticks taking whichever way round the wrapping PCR clock is shorter, so a
+// PCR just past the wrap comes out slightly ahead of one just before it
+pub fn pcr_diff(a: u64, b: u64) -> i64 {
+    let forward = (b % PCR_WRAP + PCR_WRAP - a % PCR_WRAP) % PCR_WRAP;
+    if forward > PCR_WRAP / 2 {
+        return forward as i64 - PCR_WRAP as i64;
+    }
+    return forward as i64;
+}
+
+// shifts the timeline of a stream being appended to another so the joined stream keeps
+// counting up, PCRs always and PES PTS/DTS when rewrite_pes is set
+pub struct TimelineSplicer {
+    pcr_offset: u64,
+    rewrite_pes: bool,
+}
+
+impl TimelineSplicer {
+    pub fn new(pcr_offset: u64, rewrite_pes: bool) -> TimelineSplicer {
+        return TimelineSplicer {
+            pcr_offset: pcr_offset % PCR_WRAP,
+            rewrite_pes: rewrite_pes,
+        };
+    }
+
+    pub fn rebase(&self, pkt: Packet) -> PacketData {
+        let mut data = *pkt.data();
+        if pkt.has_pcr() {
+            data = Packet::with_pcr(data, (pkt.pcr() + self.pcr_offset) % PCR_WRAP);
+        }
+        if self.rewrite_pes && pkt.pusi() && pkt.has_payload() {
+            let pes = pkt.payload_data();
+            if pes.valid_start_code() {
+                let pes_offset = 188 - pes.len();
+                let ts_offset = self.pcr_offset / 300;
+                if let Some(pts) = pes.pts() {
+                    let pos = pes_offset + 9;
+                    write_timestamp(&mut data[pos..pos + 5], (pts + ts_offset) % PTS_WRAP);
+                }
+                if let Some(dts) = pes.dts() {
+                    let pos = pes_offset + 14;
+                    write_timestamp(&mut data[pos..pos + 5], (dts + ts_offset) % PTS_WRAP);
+                }
+            }
+        }
+        return data;
+    }
+}
+
+// fills in the PTS of access units that arrive without one by counting frames on from the
+// last PTS that was seen, frame_rate is num/den frames per second e.g. 30000/1001
+pub struct PtsInterpolator {
+    frame_rate_num: u64,
+    frame_rate_den: u64,
+    last_known: u64,
+    frames_since: u64,
+}
+
+impl PtsInterpolator {
+    pub fn new(frame_rate_num: u32, frame_rate_den: u32) -> PtsInterpolator {
+        return PtsInterpolator {
+            frame_rate_num: frame_rate_num.max(1) as u64,
+            frame_rate_den: frame_rate_den as u64,
+            last_known: 0,
+            frames_since: 0,
+        };
+    }
+
+    // until the first PTS turns up frames are counted from 0
+    pub fn next(&mut self, au_has_pts: Option<u64>) -> u64 {
+        if let Some(pts) = au_has_pts {
+            self.last_known = pts;
+            self.frames_since = 0;
+            return pts;
+        }
+        self.frames_since += 1;
+        // scaling the whole frame count keeps rates like 29.97 from drifting
+        let elapsed = self.frames_since * 90_000 * self.frame_rate_den / self.frame_rate_num;
+        return (self.last_known + elapsed) % PTS_WRAP;
+    }
+}
+
+// tracks PCR jitter on one PID against the bitrate implied by the PCRs seen so far, each
+// PCR is compared with where the packet count since the first PCR says it should fall. A
+// discontinuity flagged PCR starts a new timebase and the baseline is taken afresh from it
+pub struct PcrAnalyzer {
+    pcr_pid: u16,
+    packets: u64,
+    baseline: Option<(u64, u64)>,
+    previous: Option<(u64, u64)>,
+    last_jitter: Option<i64>,
+    max_jitter: u64,
+    resets: usize,
+    tolerance_ns: u64,
+    report: PcrReport,
+}
+
+// TR 101 290 PCR_accuracy
+pub const PCR_ACCURACY_NS: u64 = 500;
+
+// one PCR that was off by more than the tolerance, packet counting from the first pushed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PcrOffender {
+    pub packet: u64,
+    pub error_ns: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PcrReport {
+    // PCRs that had a prediction to be measured against
+    pub checked: usize,
+    pub out_of_tolerance: usize,
+    // the furthest off of the out of tolerance PCRs
+    pub worst: Option<PcrOffender>,
+    // largest error either way across every PCR checked, the PCR_overall_jitter bound
+    pub overall_jitter_ns: u64,
+}
+
+impl PcrAnalyzer {
+    pub fn new(pcr_pid: u16) -> PcrAnalyzer {
+        return PcrAnalyzer::with_tolerance_ns(pcr_pid, PCR_ACCURACY_NS);
+    }
+
+    pub fn with_tolerance_ns(pcr_pid: u16, tolerance_ns: u64) -> PcrAnalyzer {
+        return PcrAnalyzer {
+            pcr_pid: pcr_pid,
+            packets: 0,
+            baseline: None,
+            previous: None,
+            last_jitter: None,
+            max_jitter: 0,
+            resets: 0,
+            tolerance_ns: tolerance_ns,
+            report: PcrReport::default(),
+        };
+    }
+
+    pub fn push(&mut self, pkt: &Packet) {
+        let packets = self.packets;
+        self.packets += 1;
+        if pkt.pid() != self.pcr_pid || !pkt.has_pcr() {
+            return;
+        }
+        let pcr = pkt.pcr();
+        let (base_pcr, base_packets) = match self.baseline {
+            Some(baseline) if !pkt.is_discontinuity() => baseline,
+            _ => {
+                if self.baseline.is_some() {
+                    self.resets += 1;
+                }
+                self.baseline = Some((pcr, packets));
+                self.previous = None;
+                self.last_jitter = None;
+                return;
+            }
+        };
+        let elapsed = ((pcr + PCR_WRAP - base_pcr) % PCR_WRAP) as i64;
+        if let Some((prev_pcr, prev_packets)) = self.previous {
+            // packet rate from the baseline up to the previous PCR predicts this one
+            let prev_elapsed = ((prev_pcr + PCR_WRAP - base_pcr) % PCR_WRAP) as i128;
+            let predicted = prev_elapsed * (packets - base_packets) as i128
+                / (prev_packets - base_packets) as i128;
+            let jitter = elapsed - predicted as i64;
+            self.last_jitter = Some(jitter);
+            self.max_jitter = self.max_jitter.max(jitter.unsigned_abs());
+            self.classify(packets, jitter);
+        }
+        self.previous = Some((pcr, packets));
+    }
+
+    // 27MHz ticks the latest PCR was off from prediction, positive when late
+    pub fn last_jitter(&self) -> Option<i64> {
+        return self.last_jitter;
+    }
+
+    pub fn max_jitter(&self) -> u64 {
+        return self.max_jitter;
+    }
+
+    // number of times a discontinuity restarted the timebase
+    pub fn resets(&self) -> usize {
+        return self.resets;
+    }
+
+    // how the PCRs so far measure up against the tolerance, PCR_ACCURACY_NS unless built
+    // with_tolerance_ns
+    pub fn report(&self) -> PcrReport {
+        return self.report;
+    }
+
+    fn classify(&mut self, packet: u64, jitter: i64) {
+        let error_ns = pcr_to_nanos(jitter.unsigned_abs()) as i64 * jitter.signum();
+        let report = &mut self.report;
+        report.checked += 1;
+        report.overall_jitter_ns = report.overall_jitter_ns.max(error_ns.unsigned_abs());
+        if error_ns.unsigned_abs() <= self.tolerance_ns {
+            return;
+        }
+        report.out_of_tolerance += 1;
+        let worse = match report.worst {
+            Some(worst) => error_ns.unsigned_abs() > worst.error_ns.unsigned_abs(),
+            None => true,
+        };
+        if worse {
+            report.worst = Some(PcrOffender {
+                packet: packet,
+                error_ns: error_ns,
+            });
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GopStats {
+    pub avg_ms: f64,
+    pub max_ms: f64,
+    // random access points seen on the PID
+    pub count: usize,
+}
+
+// spacing of the random_access_indicator packets on pid, timed by interpolating between the
+// PCRs on pcr_pid by packet position. None without two PCRs to time against
+pub fn gop_stats(buf: &[u8], pid: u16, pcr_pid: u16) -> Option<GopStats> {
+    // (packet index, PCR unwrapped to count on from the first)
+    let mut pcrs: Vec<(usize, i64)> = Vec::new();
+    let mut last_pcr = 0;
+    let mut random_access = Vec::new();
+    for (index, window) in buf.chunks_exact(188).enumerate() {
+        let pkt = Packet::new(window.try_into().unwrap());
+        if pkt.sync() != 0x47 {
+            continue;
+        }
+        if pkt.pid() == pcr_pid && pkt.has_pcr() {
+            let elapsed = match pcrs.last() {
+                Some((_, elapsed)) => elapsed + pcr_diff(last_pcr, pkt.pcr()),
+                None => 0,
+            };
+            last_pcr = pkt.pcr();
+            pcrs.push((index, elapsed));
+        }
+        if pkt.pid() == pid && pkt.is_random_access() {
+            random_access.push(index);
+        }
+    }
+    if pcrs.len() < 2 {
+        return None;
+    }
+    let times: Vec<f64> = random_access
+        .iter()
+        .map(|index| {
+            // the PCR pair either side, or the nearest pair at the ends
+            let after = pcrs
+                .iter()
+                .position(|(pcr_index, _)| pcr_index > index)
+                .unwrap_or(pcrs.len() - 1)
+                .max(1);
+            let (a_index, a_ticks) = pcrs[after - 1];
+            let (b_index, b_ticks) = pcrs[after];
+            let ticks_per_packet = (b_ticks - a_ticks) as f64 / (b_index - a_index) as f64;
+            return a_ticks as f64 + (*index as f64 - a_index as f64) * ticks_per_packet;
+        })
+        .collect();
+    let intervals: Vec<f64> = times
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]) / 27_000.0)
+        .collect();
+    let mut stats = GopStats {
+        avg_ms: 0.0,
+        max_ms: 0.0,
+        count: random_access.len(),
+    };
+    if !intervals.is_empty() {
+        stats.avg_ms = intervals.iter().sum::<f64>() / intervals.len() as f64;
+        stats.max_ms = intervals.iter().cloned().fold(0.0, f64::max);
+    }
+    return Some(stats);
+}
+
+// pairs every packet with a PCR interpolated by packet position between the PCRs on pcr_pid,
+// so packets are held back until the next PCR arrives. Packets before the first PCR and
+// after the last are extrapolated from the nearest interval, and if the stream only ever
+// carries one PCR everything gets that value. With no PCR at all nothing is yielded
+pub struct WithPcr<I> {
+    inner: I,
+    pcr_pid: u16,
+    // packets waiting on the next PCR, with their index in the stream
+    pending: VecDeque<(u64, Packet)>,
+    ready: VecDeque<(u64, Packet)>,
+    // (index, pcr) of the last PCR seen
+    last: Option<(u64, u64)>,
+    // (ticks, packets) between the last two PCRs
+    rate: Option<(i64, u64)>,
+    index: u64,
+}
+
+impl<I: Iterator<Item = Packet>> WithPcr<I> {
+    // moves pending packets over to ready timed against the last PCR at the given rate
+    fn release(&mut self, rate: (i64, u64)) {
+        let (base_index, base_pcr) = match self.last {
+            Some(last) => last,
+            None => return,
+        };
+        let (ticks, packets) = rate;
+        for (index, pkt) in self.pending.drain(..) {
+            let offset = index as i128 - base_index as i128;
+            let step = if packets == 0 {
+                0
+            } else {
+                offset * ticks as i128 / packets as i128
+            };
+            let pcr = (base_pcr as i128 + step).rem_euclid(PCR_WRAP as i128) as u64;
+            self.ready.push_back((pcr, pkt));
+        }
+    }
+}
+
+impl<I: Iterator<Item = Packet>> Iterator for WithPcr<I> {
+    type Item = (u64, Packet);
+
+    fn next(&mut self) -> Option<(u64, Packet)> {
+        while self.ready.is_empty() {
+            let pkt = match self.inner.next() {
+                Some(pkt) => pkt,
+                None => {
+                    let rate = self.rate.unwrap_or((0, 1));
+                    self.release(rate);
+                    self.pending.clear();
+                    break;
+                }
+            };
+            let index = self.index;
+            self.index += 1;
+            let pcr = if pkt.pid() == self.pcr_pid && pkt.has_pcr() {
+                Some(pkt.pcr())
+            } else {
+                None
+            };
+            self.pending.push_back((index, pkt));
+            if let Some(pcr) = pcr {
+                if let Some((last_index, last_pcr)) = self.last {
+                    let rate = (pcr_diff(last_pcr, pcr), index - last_index);
+                    self.rate = Some(rate);
+                    self.release(rate);
+                }
+                self.last = Some((index, pcr));
+            }
+        }
+        return self.ready.pop_front();
+    }
+}
+
+pub trait WithPcrExt: Iterator<Item = Packet> + Sized {
+    fn with_pcr(self, pcr_pid: u16) -> WithPcr<Self>;
+}
+
+impl<I: Iterator<Item = Packet>> WithPcrExt for I {
+    fn with_pcr(self, pcr_pid: u16) -> WithPcr<I> {
+        return WithPcr {
+            inner: self,
+            pcr_pid: pcr_pid,
+            pending: VecDeque::new(),
+            ready: VecDeque::new(),
+            last: None,
+            rate: None,
+            index: 0,
+        };
+    }
+}
@@ -0,0 +1,130 @@
+use crate::continuity::{Continuity, ContinuityChecker};
+use crate::packet::{Packet, PacketData, PacketHeader};
+use crate::psi::{
+    calc_crc32, ElementaryStream, SectionAssembler, TableHeader, TableSyntaxSection, PAT, PMT, PSI,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryInto;
+
+// largest section a 12 bit section_length allows, plus the 3 header bytes
+const MAX_SECTION_LEN: usize = 3 + 4095;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct StreamReport {
+    pub packets: usize,
+    pub sync_errors: usize,
+    pub cc_errors: usize,
+    pub crc_errors: usize,
+}
+
+impl StreamReport {
+    pub fn is_clean(&self) -> bool {
+        return self.sync_errors == 0 && self.cc_errors == 0 && self.crc_errors == 0;
+    }
+}
+
+// one pass sanity check of a muxed stream: sync bytes, continuity on every PID and the CRC
+// of each section on PID 0 and the PMT PIDs the PAT points at
+pub fn validate_stream(buf: &[u8]) -> StreamReport {
+    let mut report = StreamReport::default();
+    let mut continuity = ContinuityChecker::new();
+    let mut psi_pids = BTreeSet::new();
+    psi_pids.insert(0u16);
+    // each PID's partial section lives in its own scratch until the section completes
+    let mut assemblers: BTreeMap<u16, (SectionAssembler, Vec<u8>)> = BTreeMap::new();
+    for window in buf.chunks_exact(188) {
+        report.packets += 1;
+        let pkt = Packet::new(window.try_into().unwrap());
+        if pkt.sync() != 0x47 {
+            report.sync_errors += 1;
+            continue;
+        }
+        if let Continuity::CcError { .. } = continuity.check(&pkt) {
+            report.cc_errors += 1;
+        }
+        if !psi_pids.contains(&pkt.pid()) {
+            continue;
+        }
+        let is_pat = pkt.pid() == 0;
+        let mut pmt_pids = Vec::new();
+        let crc_errors = &mut report.crc_errors;
+        let (assembler, scratch) = assemblers
+            .entry(pkt.pid())
+            .or_insert_with(|| (SectionAssembler::new(), vec![0u8; MAX_SECTION_LEN]));
+        let _ = assembler.push_into(&pkt, scratch, |section| {
+            if !section.has_syntax_section() || section.len() < 12 {
+                return;
+            }
+            if calc_crc32(section) != section.crc32() {
+                *crc_errors += 1;
+                return;
+            }
+            if is_pat && section.table_id() == 0 {
+                for entry in section.table_data().chunks_exact(4) {
+                    // program 0 points at the network PID rather than a PMT
+                    if entry.program_num() != 0 {
+                        pmt_pids.push(entry.program_map_pid());
+                    }
+                }
+            }
+        });
+        psi_pids.extend(pmt_pids);
+    }
+    return report;
+}
+
+// sanity check for a packet out of one of the create_* PSI builders: every section in it
+// has to be whole with a good CRC, and PATs and PMTs have to parse back cleanly to the end
+// of their table data
+pub fn roundtrip_ok(pkt: &PacketData) -> bool {
+    let pkt = Packet::new(*pkt);
+    if pkt.sync() != 0x47 || !pkt.is_section_start() {
+        return false;
+    }
+    let tables = match pkt.tables() {
+        Some(tables) => tables,
+        None => return false,
+    };
+    let mut pos = 0;
+    let mut sections = 0;
+    while pos + 3 <= tables.len() && tables[pos] != 0xFF {
+        let current = &tables[pos..];
+        let section_len = current.section_length();
+        if !current.is_complete(section_len) || section_len < 9 || !current.has_syntax_section() {
+            return false;
+        }
+        let section = current.section_data();
+        if !section.valid_syntax() || calc_crc32(section) != section.crc32() {
+            return false;
+        }
+        let table = section.table_data();
+        let structure_ok = match section.table_id() {
+            0x00 => table.len() % 4 == 0 && table.chunks_exact(4).all(|entry| entry.valid_pat()),
+            0x02 => pmt_structure_ok(table),
+            _ => true,
+        };
+        if !structure_ok {
+            return false;
+        }
+        sections += 1;
+        pos += section.len();
+    }
+    return sections > 0 && tables[pos..].iter().all(|b| *b == 0xFF);
+}
+
+// the program info and ES loop have to account for every byte of the table data
+fn pmt_structure_ok(pmt: &[u8]) -> bool {
+    if pmt.len() < 4 || !pmt.valid_pmt() || 4 + pmt.program_info_len() as usize > pmt.len() {
+        return false;
+    }
+    let es = pmt.elementary_streams();
+    let mut pos = 0;
+    while pos < es.len() {
+        let entry = &es[pos..];
+        if entry.len() < 5 || !entry.valid_stream() {
+            return false;
+        }
+        pos += 5 + entry.es_info_len() as usize;
+    }
+    return pos == es.len();
+}